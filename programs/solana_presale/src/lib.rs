@@ -1,9 +1,11 @@
-use anchor_lang::{prelude::*, solana_program};
+use anchor_lang::{
+    prelude::*,
+    solana_program::{self, keccak},
+};
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{transfer, Mint, Token, TokenAccount, Transfer},
+    token::{spl_token::native_mint, transfer, Mint, Token, TokenAccount, Transfer},
 };
-use std::str::FromStr;
 
 declare_id!("dseD4adu7DMFkPUkVY6nSWSRePq2gAzftGQZYuAxRbg");
 
@@ -17,13 +19,21 @@ pub mod solana_presale {
     pub fn initializer(
         ctx: Context<Initializer>,
         start_time: u64,
+        end_time: u64,
         price_per_token_in_sol: u64,
         price_per_token_in_usdc: u64,
     ) -> Result<()> {
         let presale = &mut ctx.accounts.presale;
         require!(!presale.is_initialized, CustomError::AlreadyInitialized);
+        // 0 means no end time yet, the presale can only be finalized once one is set
+        // with set_end_time and has passed, or a hard cap is reached
+        require!(
+            end_time == 0 || end_time > start_time,
+            CustomError::WrongTime
+        );
 
         presale.start_time = start_time;
+        presale.end_time = end_time;
         presale.price_per_token_in_sol = price_per_token_in_sol; // 0.000368664 sol  = 368664
         presale.price_per_token_in_usdc = price_per_token_in_usdc; // 0.000368664 sol  = 368664
         presale.is_live = true;
@@ -31,6 +41,8 @@ pub mod solana_presale {
         presale.sol_amount_raised = 0;
         presale.token_mint = ctx.accounts.token_mint.key();
         presale.authority = ctx.accounts.signer.key();
+        presale.usdc_mint = ctx.accounts.usdc_mint.key();
+        presale.apply_defaults();
 
         let staking = &mut ctx.accounts.staking;
 
@@ -40,20 +52,175 @@ pub mod solana_presale {
         staking.total_tokens_rewarded = 0;
         staking.token_mint = ctx.accounts.token_mint.key();
         staking.staking_start_date = cur_timestamp;
+        staking.last_reward_time = cur_timestamp;
         staking.allow_claiming = false;
         staking.authority = ctx.accounts.signer.key();
 
         Ok(())
     }
 
+    // grows a presale account created by an older version of the program to the current
+    // PresaleInfo size. the new settings get the initializer defaults and `usdc_mint` if the
+    // account has none yet, the rest starts zeroed. the authority pays the extra rent.
+    pub fn migrate_presale(ctx: Context<MigratePresale>, usdc_mint: Pubkey) -> Result<()> {
+        let presale = ctx.accounts.presale.to_account_info();
+        grow_account(
+            &presale,
+            &ctx.accounts.signer,
+            &ctx.accounts.system_program,
+            8 + std::mem::size_of::<PresaleInfo>(),
+        )?;
+
+        // the old layout can't be deserialized, so the authority is checked after the realloc
+        let mut presale_data = PresaleInfo::try_deserialize(&mut &presale.try_borrow_data()?[..])?;
+        require!(
+            presale_data.authority == ctx.accounts.signer.key(),
+            CustomError::Unauthorized
+        );
+
+        presale_data.apply_defaults();
+        if presale_data.usdc_mint == Pubkey::default() {
+            presale_data.usdc_mint = usdc_mint;
+        }
+        // without deferred delivery the sold tokens were sent out on purchase
+        if presale_data.tokens_delivered == 0 && !presale_data.deferred_delivery {
+            presale_data.tokens_delivered = presale_data
+                .total_tokens_sold
+                .saturating_add(presale_data.total_bonus_tokens);
+        }
+        presale_data.try_serialize(&mut &mut presale.try_borrow_mut_data()?[..])?;
+        Ok(())
+    }
+
+    // grows the staking account of an older version to the current StakingInfo size.
+    // rewards accrue through acc_reward_per_token from now on. the rewards the older version
+    // owes until now are reserved in rewards_accrued and paid by migrate_staking_data.
+    // needs the migrated presale for the authority check.
+    pub fn migrate_staking(ctx: Context<MigrateStaking>) -> Result<()> {
+        let staking = ctx.accounts.staking.to_account_info();
+        grow_account(
+            &staking,
+            &ctx.accounts.signer,
+            &ctx.accounts.system_program,
+            8 + std::mem::size_of::<StakingInfo>(),
+        )?;
+
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        let mut staking_data = StakingInfo::try_deserialize(&mut &staking.try_borrow_data()?[..])?;
+        staking_data.acc_reward_per_token = 0;
+        staking_data.last_reward_time = cur_timestamp;
+        staking_data.legacy_end_time = cur_timestamp;
+        staking_data.legacy_total_staked = staking_data.total_tokens_staked;
+        staking_data.legacy_unmigrated_stake = staking_data.total_tokens_staked;
+        if staking_data.total_tokens_staked > 0 {
+            staking_data.legacy_rewards_reserved =
+                legacy_rewards_bound(staking_data.staking_start_date, cur_timestamp)?;
+        }
+        staking_data.rewards_accrued = staking_data
+            .total_tokens_rewarded
+            .checked_add(staking_data.legacy_rewards_reserved)
+            .ok_or(CustomError::Overflow)?;
+        staking_data.try_serialize(&mut &mut staking.try_borrow_mut_data()?[..])?;
+        Ok(())
+    }
+
+    // grows the staking position of the signer to the current StakingData size, after
+    // migrate_staking. the rewards the older version owes the position until migrate_staking
+    // become pending_rewards. the position then settles as of now, so it earns the rewards
+    // emitted since migrate_staking and its reward_debt keeps it from earning them again.
+    pub fn migrate_staking_data(ctx: Context<MigrateStakingData>) -> Result<()> {
+        let staking_data = ctx.accounts.staking_data.to_account_info();
+        grow_account(
+            &staking_data,
+            &ctx.accounts.signer,
+            &ctx.accounts.system_program,
+            8 + std::mem::size_of::<StakingData>(),
+        )?;
+
+        let mut user_staking_data =
+            StakingData::try_deserialize(&mut &staking_data.try_borrow_data()?[..])?;
+        let staking = &mut ctx.accounts.staking;
+        let legacy_rewards = legacy_rewards(
+            user_staking_data.total_staking_balance,
+            user_staking_data.stake_date,
+            staking.staking_start_date,
+            staking.legacy_total_staked,
+            staking.legacy_end_time,
+        )?
+        .min(staking.legacy_rewards_reserved);
+        user_staking_data.pending_rewards = user_staking_data
+            .pending_rewards
+            .checked_add(legacy_rewards)
+            .ok_or(CustomError::Overflow)?;
+        staking.legacy_rewards_reserved -= legacy_rewards;
+        staking.legacy_unmigrated_stake = staking
+            .legacy_unmigrated_stake
+            .saturating_sub(user_staking_data.total_staking_balance);
+        if staking.legacy_unmigrated_stake == 0 {
+            // every position is migrated, the rest of the reserve isn't owed to anyone
+            staking.rewards_accrued = staking
+                .rewards_accrued
+                .saturating_sub(staking.legacy_rewards_reserved);
+            staking.legacy_rewards_reserved = 0;
+        }
+
+        // the balance hasn't changed since acc_reward_per_token started at 0
+        user_staking_data.reward_debt = 0;
+        user_staking_data.settle_rewards(staking)?;
+        user_staking_data.try_serialize(&mut &mut staking_data.try_borrow_mut_data()?[..])?;
+        Ok(())
+    }
+
+    // grows the investment data of the signer to the current InvestmentData size, after
+    // migrate_presale. the older version sent the tokens on purchase, so they count as
+    // claimed, and what it paid in usdc is recorded for claim_payment_refund.
+    pub fn migrate_investment_data(ctx: Context<MigrateInvestmentData>) -> Result<()> {
+        let data = ctx.accounts.data.to_account_info();
+        grow_account(
+            &data,
+            &ctx.accounts.signer,
+            &ctx.accounts.system_program,
+            8 + std::mem::size_of::<InvestmentData>(),
+        )?;
+
+        let mut user_data = InvestmentData::try_deserialize(&mut &data.try_borrow_data()?[..])?;
+        user_data.claimed_tokens = user_data.number_of_tokens;
+        if user_data.usdc_investment_amount > 0 {
+            let payment_data = ctx
+                .accounts
+                .payment_data
+                .as_deref_mut()
+                .ok_or(CustomError::InvalidPaymentToken)?;
+            payment_data.investment_amount = payment_data
+                .investment_amount
+                .checked_add(user_data.usdc_investment_amount)
+                .ok_or(CustomError::Overflow)?;
+        }
+        user_data.try_serialize(&mut &mut data.try_borrow_mut_data()?[..])?;
+        Ok(())
+    }
+
     ////////////////////////////////////////////////////////////
     //                        User functions
     ////////////////////////////////////////////////////////////
 
     // function for users to invest in presale using sol and get tokens in return.
-    // there is no vesting
-    // min investment is 0.5 sol and max investment is 200 sol
-    pub fn invest(ctx: Context<Invest>, value: u64, payment_token: u8) -> Result<()> {
+    // passing a payment mint pays with that spl token instead of sol.
+    // min investment is 0.5 sol and max investment is 200 sol per wallet by default,
+    // see set_investment_limits
+    // while the whitelist phase is active buyers pass a merkle proof for their allocation,
+    // otherwise `proof` can be empty and `allocation` 0
+    // fails if the purchase yields fewer than `min_tokens_out` tokens, e.g. after a price change
+    // passing a referrer credits it with the referred volume and the referral bonus, which
+    // is reserved against the token cap and the round allocation like the bought tokens.
+    // a wallet bound to a referrer has to pass it on every purchase
+    pub fn invest(
+        ctx: Context<Invest>,
+        value: u64,
+        min_tokens_out: u64,
+        proof: Vec<[u8; 32]>,
+        allocation: u64,
+    ) -> Result<()> {
         let presale_data = &mut ctx.accounts.presale;
         let user_data = &mut ctx.accounts.data;
 
@@ -65,42 +232,73 @@ pub mod solana_presale {
             cur_timestamp >= presale_data.start_time,
             CustomError::PresaleNotStarted
         );
-        // Ensure the payment token is either 0 (SOL) or 1 (USDC)
         require!(
-            payment_token == 0 || payment_token == 1,
-            CustomError::InvalidPaymentToken
+            !presale_data.has_ended(cur_timestamp),
+            CustomError::PresaleEnded
         );
+        let purchase = price_purchase(
+            presale_data,
+            &mut ctx.accounts.round,
+            &mut ctx.accounts.next_round,
+            &ctx.accounts.price_feed,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.bonus_config,
+            ctx.accounts.referrer.is_some(),
+            value,
+            cur_timestamp,
+        )?;
+        let value = purchase.value;
+        // bonus tokens are delivered with the bought ones, they count against the hard cap
+        // but not towards the soft cap
+        let number_of_tokens = purchase.number_of_tokens + purchase.bonus_tokens;
+        require!(
+            number_of_tokens >= min_tokens_out,
+            CustomError::SlippageExceeded
+        );
+        check_investment_limits(presale_data, user_data, &purchase)?;
+        check_whitelist(
+            presale_data,
+            user_data,
+            &ctx.accounts.from.key(),
+            &proof,
+            allocation,
+            number_of_tokens,
+            cur_timestamp,
+        )?;
+        record_referral(
+            presale_data,
+            user_data,
+            &ctx.accounts.from.key(),
+            ctx.accounts.referrer.as_ref(),
+            ctx.accounts.referrer_data.as_deref(),
+            ctx.accounts.referral_data.as_deref_mut(),
+            &purchase,
+        )?;
 
-        let number_of_tokens = if payment_token == 0 {
-            // SOL Payment
-            require!(
-                value >= 500000000 && value <= 200000000000,
-                CustomError::WrongAmount
-            );
-            value * PRECISION / presale_data.price_per_token_in_sol
-        } else {
-            // USDC Payment
-            require!(
-                value >= 100_000000 && value <= 40_000_000000,
-                CustomError::WrongAmount
-            );
-            value * PRECISION / presale_data.price_per_token_in_usdc
-        };
-
-        if payment_token == 0 {
-            user_data.sol_investment_amount += value;
-            presale_data.sol_amount_raised += value;
-        } else {
-            user_data.usdc_investment_amount += value;
-            presale_data.usdc_amount_raised += value;
+        user_data.sol_investment_amount += purchase.sol_value;
+        presale_data.sol_amount_raised += purchase.sol_value;
+        user_data.usdc_investment_amount += purchase.usd_value;
+        presale_data.usdc_amount_raised += purchase.usd_value;
+        if let Some(payment_mint) = ctx.accounts.payment_mint.as_deref_mut() {
+            let payment_data = ctx
+                .accounts
+                .payment_data
+                .as_deref_mut()
+                .ok_or(CustomError::InvalidPaymentToken)?;
+            payment_mint.record_payment(payment_data, value)?;
         }
         user_data.number_of_tokens += number_of_tokens;
-        presale_data.total_tokens_sold += number_of_tokens;
+        user_data.bonus_tokens += purchase.bonus_tokens;
+        presale_data.total_tokens_sold += purchase.number_of_tokens;
+        presale_data.total_bonus_tokens += purchase.bonus_tokens;
+        if presale_data.hard_cap_reached() {
+            presale_data.is_live = false;
+        }
 
         let from_account = &ctx.accounts.from;
         let presale = presale_data.to_account_info();
 
-        if payment_token == 0 {
+        if ctx.accounts.payment_mint.is_none() {
             // Transfer Sol from investor to presale account
             let transfer_instruction =
                 solana_program::system_instruction::transfer(from_account.key, presale.key, value);
@@ -115,19 +313,36 @@ pub mod solana_presale {
                 ],
             )?;
         } else {
-            // Transfer USDC from investor to presale account
+            // Transfer the payment mint from investor to presale account
+            let signer_payment_account = ctx
+                .accounts
+                .signer_payment_account
+                .as_ref()
+                .ok_or(CustomError::InvalidPaymentToken)?;
+            let presale_payment_account = ctx
+                .accounts
+                .presale_payment_account
+                .as_ref()
+                .ok_or(CustomError::InvalidPaymentToken)?;
             transfer(
                 CpiContext::new(
                     ctx.accounts.token_program.to_account_info(),
                     Transfer {
-                        from: ctx.accounts.signer_usdc_account.to_account_info(),
-                        to: ctx.accounts.presale_usdc_account.to_account_info(),
+                        from: signer_payment_account.to_account_info(),
+                        to: presale_payment_account.to_account_info(),
                         authority: ctx.accounts.signer.to_account_info(),
                     },
                 ),
                 value,
             )?;
         }
+        // with deferred delivery the tokens are only credited and claimed after TGE
+        if ctx.accounts.presale.deferred_delivery {
+            return Ok(());
+        }
+        ctx.accounts.data.claimed_tokens += number_of_tokens;
+        ctx.accounts.presale.tokens_delivered += number_of_tokens;
+
         // Transfer Presale Tokens to Investor
         transfer(
             CpiContext::new_with_signer(
@@ -144,62 +359,140 @@ pub mod solana_presale {
         Ok(())
     }
 
-    pub fn buy_and_stake(ctx: Context<BuyAndStake>, value: u64, payment_token: u8) -> Result<()> {
+    // delivers tokens credited by `invest` in deferred delivery mode once TGE has passed
+    pub fn claim_tokens(ctx: Context<ClaimTokens>) -> Result<()> {
+        let presale_data = &ctx.accounts.presale;
+        require!(!presale_data.is_failed(), CustomError::PresaleFailed);
+        require!(!presale_data.has_vesting(), CustomError::VestingEnabled);
+
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        require!(
+            presale_data.tge_time > 0 && cur_timestamp >= presale_data.tge_time,
+            CustomError::ClaimLocked
+        );
+
+        let user_data = &mut ctx.accounts.data;
+        let claimable = user_data.number_of_tokens - user_data.claimed_tokens;
+        require!(claimable > 0, CustomError::ZeroAmount);
+
+        user_data.claimed_tokens = user_data.number_of_tokens;
+        ctx.accounts.presale.tokens_delivered += claimable;
+
+        transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.presale_token_account.to_account_info(),
+                    to: ctx.accounts.signer_token_account.to_account_info(),
+                    authority: ctx.accounts.presale.to_account_info(),
+                },
+                &[&[PRESALE_SEED, &[ctx.bumps.presale]][..]],
+            ),
+            claimable,
+        )?;
+        Ok(())
+    }
+
+    pub fn buy_and_stake(
+        ctx: Context<BuyAndStake>,
+        value: u64,
+        min_tokens_out: u64,
+        proof: Vec<[u8; 32]>,
+        allocation: u64,
+    ) -> Result<()> {
         let presale_data = &mut ctx.accounts.presale;
         let staking_data = &mut ctx.accounts.staking;
         let user_staking_data = &mut ctx.accounts.staking_data;
         let user_data = &mut ctx.accounts.investment_data;
 
         require!(presale_data.is_live, CustomError::PresaleNotLive);
-        // Ensure the payment token is either 0 (SOL) or 1 (USDC)
-         require!(
-            payment_token == 0 || payment_token == 1,
-            CustomError::InvalidPaymentToken
-        );
         let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
         require!(
             cur_timestamp >= presale_data.start_time,
             CustomError::PresaleNotStarted
         );
+        require!(
+            !presale_data.has_ended(cur_timestamp),
+            CustomError::PresaleEnded
+        );
         if !user_staking_data.is_first_time {
             let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
             user_staking_data.stake_date = cur_timestamp;
             user_staking_data.is_first_time = true;
         }
 
-        let number_of_tokens = if payment_token == 0 {
-            // SOL Payment
-            require!(
-                value >= 500000000 && value <= 200000000000,
-                CustomError::WrongAmount
-            );
-            value * PRECISION / presale_data.price_per_token_in_sol
-        } else {
-            // USDC Payment
-            require!(
-                value >= 100_000000 && value <= 40_000_000000,
-                CustomError::WrongAmount
-            );
-            value * PRECISION / presale_data.price_per_token_in_usdc
-        };
-        if payment_token == 0 {
-            user_data.sol_investment_amount += value;
-            presale_data.sol_amount_raised += value;
-        } else {
-            user_data.usdc_investment_amount += value;
-            presale_data.usdc_amount_raised += value;
+        let purchase = price_purchase(
+            presale_data,
+            &mut ctx.accounts.round,
+            &mut ctx.accounts.next_round,
+            &ctx.accounts.price_feed,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.bonus_config,
+            ctx.accounts.referrer.is_some(),
+            value,
+            cur_timestamp,
+        )?;
+        let value = purchase.value;
+        // bonus tokens are delivered with the bought ones, they count against the hard cap
+        // but not towards the soft cap
+        let number_of_tokens = purchase.number_of_tokens + purchase.bonus_tokens;
+        require!(
+            number_of_tokens >= min_tokens_out,
+            CustomError::SlippageExceeded
+        );
+        check_investment_limits(presale_data, user_data, &purchase)?;
+        check_whitelist(
+            presale_data,
+            user_data,
+            &ctx.accounts.signer.key(),
+            &proof,
+            allocation,
+            number_of_tokens,
+            cur_timestamp,
+        )?;
+        record_referral(
+            presale_data,
+            user_data,
+            &ctx.accounts.signer.key(),
+            ctx.accounts.referrer.as_ref(),
+            ctx.accounts.referrer_data.as_deref(),
+            ctx.accounts.referral_data.as_deref_mut(),
+            &purchase,
+        )?;
+        user_data.sol_investment_amount += purchase.sol_value;
+        presale_data.sol_amount_raised += purchase.sol_value;
+        user_data.usdc_investment_amount += purchase.usd_value;
+        presale_data.usdc_amount_raised += purchase.usd_value;
+        if let Some(payment_mint) = ctx.accounts.payment_mint.as_deref_mut() {
+            let payment_data = ctx
+                .accounts
+                .payment_data
+                .as_deref_mut()
+                .ok_or(CustomError::InvalidPaymentToken)?;
+            payment_mint.record_payment(payment_data, value)?;
         }
 
-        presale_data.total_tokens_sold += number_of_tokens;
+        user_data.bonus_tokens += purchase.bonus_tokens;
+        presale_data.total_tokens_sold += purchase.number_of_tokens;
+        presale_data.total_bonus_tokens += purchase.bonus_tokens;
+        if presale_data.hard_cap_reached() {
+            presale_data.is_live = false;
+        }
 
+        staking_data.update_rewards(&ctx.accounts.reward_schedule, cur_timestamp)?;
+        user_staking_data.settle_rewards(staking_data)?;
         staking_data.total_tokens_staked += number_of_tokens;
 
         // Update user staking balance
         user_staking_data.total_staking_balance += number_of_tokens;
+        user_staking_data.presale_tokens += number_of_tokens;
+        user_staking_data.sync_reward_debt(staking_data)?;
+        user_data.staked_tokens += number_of_tokens;
+        presale_data.tokens_delivered += number_of_tokens;
         let from_account = &ctx.accounts.from;
         let presale = presale_data.to_account_info();
 
-        if payment_token == 0 {
+        if ctx.accounts.payment_mint.is_none() {
             // Transfer Sol from investor to presale account
             let transfer_instruction =
                 solana_program::system_instruction::transfer(from_account.key, presale.key, value);
@@ -214,13 +507,23 @@ pub mod solana_presale {
                 ],
             )?;
         } else {
-            // Handle USDC Transfer
+            // Transfer the payment mint from investor to presale account
+            let signer_payment_account = ctx
+                .accounts
+                .signer_payment_account
+                .as_ref()
+                .ok_or(CustomError::InvalidPaymentToken)?;
+            let presale_payment_account = ctx
+                .accounts
+                .presale_payment_account
+                .as_ref()
+                .ok_or(CustomError::InvalidPaymentToken)?;
             transfer(
                 CpiContext::new(
                     ctx.accounts.token_program.to_account_info(),
                     Transfer {
-                        from: ctx.accounts.signer_usdc_account.to_account_info(),
-                        to: ctx.accounts.presale_usdc_account.to_account_info(),
+                        from: signer_payment_account.to_account_info(),
+                        to: presale_payment_account.to_account_info(),
                         authority: ctx.accounts.signer.to_account_info(),
                     },
                 ),
@@ -251,13 +554,16 @@ pub mod solana_presale {
         let staking = &mut ctx.accounts.staking;
 
         let user_info = &mut ctx.accounts.staking_data;
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
 
         if !user_info.is_first_time {
-            let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
             user_info.stake_date = cur_timestamp;
             user_info.is_first_time = true;
         }
 
+        // the new tokens only earn from now on
+        staking.update_rewards(&ctx.accounts.reward_schedule, cur_timestamp)?;
+        user_info.settle_rewards(staking)?;
         user_info.total_staking_balance = user_info
             .total_staking_balance
             .checked_add(amount)
@@ -266,6 +572,7 @@ pub mod solana_presale {
             .total_tokens_staked
             .checked_add(amount)
             .ok_or(CustomError::Overflow)?;
+        user_info.sync_reward_debt(staking)?;
 
         transfer(
             CpiContext::new_with_signer(
@@ -291,49 +598,15 @@ pub mod solana_presale {
         let user_info = &mut ctx.accounts.staking_data;
         require!(user_info.total_staking_balance > 0, CustomError::ZeroAmount);
 
-        let user_start_date = user_info.stake_date;
-
         let staked_amount = user_info.total_staking_balance;
-        let total_tokens_staked = staking.total_tokens_staked;
-
-        let user_share = (staked_amount * PRECISION) / total_tokens_staked;
-
         let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
-        let time_diff = (cur_timestamp - user_start_date) * PRECISION;
-
-        let stake_duration_days = ((time_diff + (DAY_DURATION / 2)) / DAY_DURATION) as u64;
-
-        let mut reward_accumulated: u64 = 0;
-        let mut remaining_days = stake_duration_days;
-
-        let mut current_month = (((user_start_date - staking.staking_start_date) * PRECISION
-            + (MONTH_DURATION / 2))
-            / MONTH_DURATION) as usize;
-
-        while remaining_days > 0 && current_month < DAILY_REWARDS_LEN {
-            let daily_reward = DAILY_REWARDS[current_month];
-            let days_in_month = std::cmp::min(remaining_days, 30);
-
-            let scaled_reward = (user_share * daily_reward * days_in_month) / PRECISION;
-
-            reward_accumulated = reward_accumulated
-                .checked_add(scaled_reward)
-                .ok_or(CustomError::Overflow)?;
-
-            remaining_days = remaining_days
-                .checked_sub(days_in_month)
-                .ok_or(CustomError::Overflow)?;
-            current_month = current_month.checked_add(1).ok_or(CustomError::Overflow)?;
-        }
-
-        // Ensure minimum 1-day reward if stake_duration_days > 0 but reward_accumulated is 0
-        if stake_duration_days >= 1 && reward_accumulated == 0 {
-            let daily_reward = DAILY_REWARDS[0]; // Use first month's reward rate
-            let min_reward = (user_share * daily_reward) / PRECISION;
-            reward_accumulated = min_reward;
-        }
-
-        require!(reward_accumulated > 0, CustomError::NoRewards);
+        require!(
+            user_info.locked_tokens(&ctx.accounts.presale, cur_timestamp) == 0,
+            CustomError::TokensLocked
+        );
+        staking.update_rewards(&ctx.accounts.reward_schedule, cur_timestamp)?;
+        user_info.settle_rewards(staking)?;
+        let reward_accumulated = user_info.claim_pending(cur_timestamp)?;
         msg!("Reward accumulated: {}", reward_accumulated);
 
         let total_payable = staked_amount
@@ -351,6 +624,7 @@ pub mod solana_presale {
 
         user_info.total_staking_balance = 0;
         user_info.is_first_time = false;
+        user_info.sync_reward_debt(staking)?;
 
         transfer(
             CpiContext::new_with_signer(
@@ -368,313 +642,3819 @@ pub mod solana_presale {
         Ok(())
     }
 
-    ////////////////////////////////////////////////////////////
-    //                        Admin functions
-    ////////////////////////////////////////////////////////////
-    pub fn allow_claiming(ctx: Context<UnlockStaking>, toggle: bool) -> Result<()> {
+    // pays the accrued rewards, the stake stays in place
+    pub fn claim_rewards(ctx: Context<Unstake>) -> Result<()> {
         let staking = &mut ctx.accounts.staking;
-        staking.allow_claiming = toggle;
-        Ok(())
-    }
-
-    pub fn change_price(ctx: Context<StopPresale>, sol_price: u64, usdc_price: u64) -> Result<()> {
-        let presale = &mut ctx.accounts.presale;
+        require!(staking.allow_claiming, CustomError::ClaimLocked);
 
-        presale.price_per_token_in_sol = sol_price;
-        presale.price_per_token_in_usdc = usdc_price;
-        Ok(())
-    }
+        let user_info = &mut ctx.accounts.staking_data;
+        require!(user_info.total_staking_balance > 0, CustomError::ZeroAmount);
 
-    pub fn toggle_presale(ctx: Context<StopPresale>, toggle: bool) -> Result<()> {
-        let presale = &mut ctx.accounts.presale;
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        staking.update_rewards(&ctx.accounts.reward_schedule, cur_timestamp)?;
+        user_info.settle_rewards(staking)?;
+        let reward_accumulated = user_info.claim_pending(cur_timestamp)?;
+        require!(reward_accumulated > 0, CustomError::NoRewards);
+        msg!("Reward accumulated: {}", reward_accumulated);
 
-        presale.is_live = toggle;
-        Ok(())
-    }
+        staking.total_tokens_rewarded = staking
+            .total_tokens_rewarded
+            .checked_add(reward_accumulated)
+            .ok_or(CustomError::Overflow)?;
 
-    pub fn update_authority(ctx: Context<TransferOwnership>, authority:Pubkey) -> Result<()> {
-        let presale = &mut ctx.accounts.presale;
-        let staking = &mut ctx.accounts.staking;
+        transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.staking_token_account.to_account_info(),
+                    to: ctx.accounts.signer_token_account.to_account_info(),
+                    authority: staking.to_account_info(),
+                },
+                &[&[STAKING_SEED, &[ctx.bumps.staking]]],
+            ),
+            reward_accumulated,
+        )?;
 
-        presale.authority = authority;
-        staking.authority = authority;
         Ok(())
     }
 
-    // emergency function for admin to withdraw tokens from staking. should be used in emergency scenario.
-    pub fn admin_withdraw_staking_tokens(ctx: Context<AdminWithdrawTokens>) -> Result<()> {
-        if ctx.accounts.staking_token_account.amount > 0{
-            transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.staking_token_account.to_account_info(),
-                        to: ctx.accounts.signer_token_account.to_account_info(),
-                        authority: ctx.accounts.staking.to_account_info(),
-                    },
-                    &[&[STAKING_SEED, &[ctx.bumps.staking]][..]],
-                ),
-                ctx.accounts.staking_token_account.amount, //  balance of the staking token account.
-            )?;
-        }
+    // opts in or out of having the rewards restaked by compound
+    pub fn set_auto_compound(ctx: Context<SetAutoCompound>, enabled: bool) -> Result<()> {
+        ctx.accounts.staking_data.auto_compound = enabled;
         Ok(())
     }
 
-    // should be called after presale end by admin to pull out remaining tokens.
-    pub fn admin_withdraw_tokens(ctx: Context<AdminWithdrawTokens>) -> Result<()> {
-       
-        if ctx.accounts.presale_token_account.amount > 0 {
-            transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.presale_token_account.to_account_info(),
-                        to: ctx.accounts.signer_token_account.to_account_info(),
-                        authority: ctx.accounts.presale.to_account_info(),
-                    },
-                    &[&[PRESALE_SEED, &[ctx.bumps.presale]][..]],
-                ),
-                ctx.accounts.presale_token_account.amount, //  balance of the presale token account.
-            )?;
-        }
+    // adds the accrued rewards of `user` to its stake. anyone can call it for stakers
+    // that enabled auto_compound, the rewards stay in the staking token account.
+    pub fn compound(ctx: Context<Compound>) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+        let user_info = &mut ctx.accounts.staking_data;
+        require!(user_info.auto_compound, CustomError::AutoCompoundDisabled);
+
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        staking.update_rewards(&ctx.accounts.reward_schedule, cur_timestamp)?;
+        user_info.settle_rewards(staking)?;
+        let reward_accumulated = user_info.claim_pending(cur_timestamp)?;
+        require!(reward_accumulated > 0, CustomError::NoRewards);
+        msg!("Reward compounded: {}", reward_accumulated);
+
+        user_info.total_staking_balance = user_info
+            .total_staking_balance
+            .checked_add(reward_accumulated)
+            .ok_or(CustomError::Overflow)?;
+        staking.total_tokens_staked = staking
+            .total_tokens_staked
+            .checked_add(reward_accumulated)
+            .ok_or(CustomError::Overflow)?;
+        staking.total_tokens_rewarded = staking
+            .total_tokens_rewarded
+            .checked_add(reward_accumulated)
+            .ok_or(CustomError::Overflow)?;
+        user_info.sync_reward_debt(staking)?;
         Ok(())
     }
 
-    pub fn admin_withdraw_usdc_and_sol(ctx: Context<AdminWithdrawUsdcSol>) -> Result<()> {
-        let usdc_balance = ctx.accounts.presale_usdc_account.amount;
-        if usdc_balance > 0 {
-            // Transfer USDC to the admin
-            transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.presale_usdc_account.to_account_info(),
-                        to: ctx.accounts.signer_usdc_account.to_account_info(),
-                        authority: ctx.accounts.presale.to_account_info(),
-                    },
-                    &[&[PRESALE_SEED, &[ctx.bumps.presale]]],
-                ),
-                usdc_balance,
-            )?;
-        }
-        let presale = &mut ctx.accounts.presale.to_account_info();
-        let recipient = &ctx.accounts.signer;
+    // withdraws `amount` of the staked tokens together with all pending rewards,
+    // the rest stays staked.
+    pub fn unstake(ctx: Context<Unstake>, amount: u64) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+        require!(staking.allow_claiming, CustomError::ClaimLocked);
+        require!(amount > 0, CustomError::ZeroAmount);
 
-        // Get the minimum rent-exempt balance for the account
-        let rent_exemption = Rent::get()?.minimum_balance(presale.data_len());
+        let user_info = &mut ctx.accounts.staking_data;
+        require!(
+            amount <= user_info.total_staking_balance,
+            CustomError::InsufficientFunds
+        );
 
-        let presale_balance = presale.lamports();
+        // presale tokens that are still vesting stay staked
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        require!(
+            user_info.total_staking_balance - amount
+                >= user_info.locked_tokens(&ctx.accounts.presale, cur_timestamp),
+            CustomError::TokensLocked
+        );
+        staking.update_rewards(&ctx.accounts.reward_schedule, cur_timestamp)?;
+        user_info.settle_rewards(staking)?;
+        let reward_accumulated = user_info.claim_pending(cur_timestamp)?;
+        msg!("Reward accumulated: {}", reward_accumulated);
 
-        if presale_balance > 0 {
-            // Ensure there is enough balance to withdraw after leaving rent
-            require!(
-                presale_balance > rent_exemption,
-                CustomError::InsufficientFunds
-            );
+        let total_payable = amount
+            .checked_add(reward_accumulated)
+            .ok_or(CustomError::Overflow)?;
 
-            // Calculate the amount to withdraw, leaving the rent-exempt balance
-            let amount_to_withdraw = presale_balance - rent_exemption;
+        staking.total_tokens_staked = staking
+            .total_tokens_staked
+            .checked_sub(amount)
+            .ok_or(CustomError::Overflow)?;
+        staking.total_tokens_rewarded = staking
+            .total_tokens_rewarded
+            .checked_add(reward_accumulated)
+            .ok_or(CustomError::Overflow)?;
 
-            **presale.to_account_info().try_borrow_mut_lamports()? -= amount_to_withdraw;
-            **recipient.to_account_info().try_borrow_mut_lamports()? += amount_to_withdraw;
+        user_info.total_staking_balance -= amount;
+        if user_info.total_staking_balance == 0 {
+            user_info.is_first_time = false;
         }
+        user_info.sync_reward_debt(staking)?;
+
+        transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.staking_token_account.to_account_info(),
+                    to: ctx.accounts.signer_token_account.to_account_info(),
+                    authority: staking.to_account_info(),
+                },
+                &[&[STAKING_SEED, &[ctx.bumps.staking]]],
+            ),
+            total_payable,
+        )?;
 
         Ok(())
     }
-}
 
-////////////////////////////////////////////////////////////
-//                        Constants
-////////////////////////////////////////////////////////////
-pub const PRESALE_SEED: &[u8] = "solana_presale".as_bytes();
-pub const DATA_SEED: &[u8] = "my_data".as_bytes();
-pub const STAKING_SEED: &[u8] = "solana_staking".as_bytes();
-pub const STAKING_DATA_SEED: &[u8] = "staking_user_data".as_bytes();
-pub const USDC_ADDRESS: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
-pub const DAILY_REWARDS: [u64; 12] = [
-    1205350000, 1237979000, 1270512000, 1303141000, 1335674000, 1368302000, 1400836000, 1433369000,
-    1465998000, 1498531000, 1531159000, 1563693000,
-];
-pub const DAILY_REWARDS_LEN: usize = DAILY_REWARDS.len();
-pub const PRECISION: u64 = 100000; // Match token decimals = 5
-pub const MONTH_DURATION: u64 = (30 * 24 * 60 * 60) * PRECISION;
+    // releases the part of the credited tokens that has vested since the last claim.
+    // tge_unlock_bps unlock at TGE, the rest vests linearly after the cliff.
+    pub fn claim_vested(ctx: Context<ClaimTokens>) -> Result<()> {
+        let presale_data = &ctx.accounts.presale;
+        require!(!presale_data.is_failed(), CustomError::PresaleFailed);
+
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        let user_data = &mut ctx.accounts.data;
+
+        let vested = presale_data.vested_tokens(user_data.number_of_tokens, cur_timestamp);
+        let claimable = vested.saturating_sub(user_data.claimed_tokens);
+        require!(claimable > 0, CustomError::NothingVested);
+
+        user_data.claimed_tokens += claimable;
+        ctx.accounts.presale.tokens_delivered += claimable;
+        msg!("Vested tokens claimed: {}", claimable);
+
+        transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.presale_token_account.to_account_info(),
+                    to: ctx.accounts.signer_token_account.to_account_info(),
+                    authority: ctx.accounts.presale.to_account_info(),
+                },
+                &[&[PRESALE_SEED, &[ctx.bumps.presale]][..]],
+            ),
+            claimable,
+        )?;
+        Ok(())
+    }
+
+    // referrers claim their bonus tokens once the presale is finalized successfully
+    pub fn claim_referral_rewards(ctx: Context<ClaimReferralRewards>) -> Result<()> {
+        let presale_data = &ctx.accounts.presale;
+        require!(
+            presale_data.is_finalized && presale_data.is_successful,
+            CustomError::ClaimLocked
+        );
+
+        let referral_data = &mut ctx.accounts.referral_data;
+        let claimable = referral_data.reward_tokens - referral_data.claimed_tokens;
+        require!(claimable > 0, CustomError::NoRewards);
+
+        referral_data.claimed_tokens += claimable;
+        ctx.accounts.presale.tokens_delivered += claimable;
+        msg!("Referral rewards claimed: {}", claimable);
+
+        transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.presale_token_account.to_account_info(),
+                    to: ctx.accounts.signer_token_account.to_account_info(),
+                    authority: ctx.accounts.presale.to_account_info(),
+                },
+                &[&[PRESALE_SEED, &[ctx.bumps.presale]][..]],
+            ),
+            claimable,
+        )?;
+        Ok(())
+    }
+
+    // if the presale was finalized below its soft cap, investors get their sol back and return
+    // the tokens they bought. tokens bought with buy_and_stake are taken out of staking.
+    // payments made with a payment mint are refunded by claim_payment_refund afterwards.
+    pub fn claim_refund(ctx: Context<ClaimRefund>) -> Result<()> {
+        let presale_data = &mut ctx.accounts.presale;
+        require!(presale_data.is_failed(), CustomError::RefundNotAvailable);
+
+        // wrapped sol counts as sol invested but stays in the presale's token account,
+        // it is refunded by claim_payment_refund
+        let wrapped_sol_data = &ctx.accounts.wrapped_sol_payment_data;
+        let wrapped_sol_refund = if wrapped_sol_data.data_is_empty() {
+            0
+        } else {
+            PaymentInvestmentData::try_deserialize(&mut &wrapped_sol_data.try_borrow_data()?[..])?
+                .investment_amount
+        };
+        let user_data = &mut ctx.accounts.data;
+        let sol_refund = user_data
+            .sol_investment_amount
+            .saturating_sub(wrapped_sol_refund);
+        require!(
+            sol_refund > 0 || user_data.number_of_tokens > 0 || user_data.staked_tokens > 0,
+            CustomError::NothingToRefund
+        );
+
+        // staked tokens that were already unstaked have to come back from the wallet,
+        // tokens that were never claimed are still in the presale
+        let staking = &mut ctx.accounts.staking;
+        staking.update_rewards(
+            &ctx.accounts.reward_schedule,
+            u64::try_from(Clock::get()?.unix_timestamp).unwrap(),
+        )?;
+        let tokens_from_staking = match ctx.accounts.staking_data.as_deref_mut() {
+            Some(user_staking_data) => {
+                // rewards earned until now stay claimable
+                user_staking_data.settle_rewards(staking)?;
+                let tokens = std::cmp::min(
+                    user_data.staked_tokens,
+                    user_staking_data.total_staking_balance,
+                );
+                user_staking_data.total_staking_balance -= tokens;
+                user_staking_data.presale_tokens = 0;
+                if user_staking_data.total_staking_balance == 0 {
+                    user_staking_data.is_first_time = false;
+                }
+                tokens
+            }
+            None => 0,
+        };
+        let tokens_from_wallet = user_data
+            .claimed_tokens
+            .checked_add(user_data.staked_tokens - tokens_from_staking)
+            .ok_or(CustomError::Overflow)?;
+
+        user_data.sol_investment_amount = 0;
+        user_data.usdc_investment_amount = 0;
+        user_data.number_of_tokens = 0;
+        user_data.claimed_tokens = 0;
+        user_data.staked_tokens = 0;
+
+        staking.total_tokens_staked = staking
+            .total_tokens_staked
+            .checked_sub(tokens_from_staking)
+            .ok_or(CustomError::Overflow)?;
+        if let Some(user_staking_data) = ctx.accounts.staking_data.as_deref_mut() {
+            user_staking_data.sync_reward_debt(staking)?;
+        }
+
+        if tokens_from_staking > 0 {
+            transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.staking_token_account.to_account_info(),
+                        to: ctx.accounts.presale_token_account.to_account_info(),
+                        authority: staking.to_account_info(),
+                    },
+                    &[&[STAKING_SEED, &[ctx.bumps.staking]]],
+                ),
+                tokens_from_staking,
+            )?;
+        }
+
+        if tokens_from_wallet > 0 {
+            let signer_token_account = ctx
+                .accounts
+                .signer_token_account
+                .as_ref()
+                .ok_or(CustomError::InsufficientFunds)?;
+            transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: signer_token_account.to_account_info(),
+                        to: ctx.accounts.presale_token_account.to_account_info(),
+                        authority: ctx.accounts.signer.to_account_info(),
+                    },
+                ),
+                tokens_from_wallet,
+            )?;
+        }
+
+        if sol_refund > 0 {
+            let presale = presale_data.to_account_info();
+            let rent_exemption = Rent::get()?.minimum_balance(presale.data_len());
+            require!(
+                presale.lamports() >= rent_exemption + sol_refund,
+                CustomError::InsufficientFunds
+            );
+
+            **presale.try_borrow_mut_lamports()? -= sol_refund;
+            **ctx
+                .accounts
+                .signer
+                .to_account_info()
+                .try_borrow_mut_lamports()? += sol_refund;
+        }
+
+        Ok(())
+    }
+
+    // refunds what was paid with one payment mint in a failed presale. the purchased tokens
+    // have to be returned with claim_refund first.
+    pub fn claim_payment_refund(ctx: Context<ClaimPaymentRefund>) -> Result<()> {
+        require!(
+            ctx.accounts.presale.is_failed(),
+            CustomError::RefundNotAvailable
+        );
+        let user_data = &ctx.accounts.data;
+        require!(
+            user_data.number_of_tokens == 0 && user_data.staked_tokens == 0,
+            CustomError::TokensNotReturned
+        );
+
+        let payment_data = &mut ctx.accounts.payment_data;
+        let refund = payment_data.investment_amount;
+        require!(refund > 0, CustomError::NothingToRefund);
+        payment_data.investment_amount = 0;
+
+        transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.presale_payment_account.to_account_info(),
+                    to: ctx.accounts.signer_payment_account.to_account_info(),
+                    authority: ctx.accounts.presale.to_account_info(),
+                },
+                &[&[PRESALE_SEED, &[ctx.bumps.presale]]],
+            ),
+            refund,
+        )?;
+        Ok(())
+    }
+
+    ////////////////////////////////////////////////////////////
+    //                        Admin functions
+    ////////////////////////////////////////////////////////////
+    pub fn allow_claiming(ctx: Context<UnlockStaking>, toggle: bool) -> Result<()> {
+        let staking = &mut ctx.accounts.staking;
+        staking.allow_claiming = toggle;
+        Ok(())
+    }
+
+    // creates the staking reward schedule, starting at the staking start date.
+    // staking needs the schedule, rewards are only emitted during its periods.
+    pub fn create_reward_schedule(
+        ctx: Context<CreateRewardSchedule>,
+        period_duration: u64,
+        emissions: Vec<u64>,
+    ) -> Result<()> {
+        require!(
+            period_duration > 0 && period_duration <= MAX_REWARD_PERIOD_DURATION,
+            CustomError::InvalidRewardSchedule
+        );
+
+        let reward_schedule = &mut ctx.accounts.reward_schedule;
+        reward_schedule.start_time = ctx.accounts.staking.staking_start_date;
+        reward_schedule.period_duration = period_duration;
+        reward_schedule.emissions = emissions;
+        Ok(())
+    }
+
+    // appends periods after the last one. rewards until now are accrued with the old
+    // schedule first, so appended periods that already started only emit from now on.
+    pub fn append_reward_periods(
+        ctx: Context<AppendRewardPeriods>,
+        emissions: Vec<u64>,
+    ) -> Result<()> {
+        require!(!emissions.is_empty(), CustomError::InvalidRewardSchedule);
+
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        let reward_schedule = &mut ctx.accounts.reward_schedule;
+        ctx.accounts
+            .staking
+            .update_rewards(reward_schedule, cur_timestamp)?;
+
+        reward_schedule.emissions.extend_from_slice(&emissions);
+        msg!("Reward schedule ends at {}", reward_schedule.end_time());
+        Ok(())
+    }
+
+    // creates the next presale round. rounds are created in order and priced independently,
+    // the flat prices in PresaleInfo are ignored once the first round exists.
+    // a round can't start before the previous one ends, which is passed as `previous_round`.
+    // rounds aren't queued as admin actions, instead their prices have to be set at least
+    // timelock_delay before the round starts, which gives buyers the same notice.
+    pub fn create_round(
+        ctx: Context<CreateRound>,
+        start_time: u64,
+        end_time: u64,
+        token_allocation: u64,
+        price_per_token_in_sol: u64,
+        price_per_token_in_usdc: u64,
+    ) -> Result<()> {
+        require!(end_time > start_time, CustomError::WrongTime);
+        require!(token_allocation > 0, CustomError::ZeroAmount);
+        require!(
+            price_per_token_in_sol > 0 && price_per_token_in_usdc > 0,
+            CustomError::InvalidPrice
+        );
+
+        let presale = &mut ctx.accounts.presale;
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        require!(
+            start_time >= cur_timestamp.saturating_add(presale.timelock_delay),
+            CustomError::RoundStartTooSoon
+        );
+        if presale.total_rounds > 0 {
+            let previous_round = ctx
+                .accounts
+                .previous_round
+                .as_ref()
+                .ok_or(CustomError::RoundRequired)?;
+            require!(
+                start_time >= previous_round.end_time,
+                CustomError::RoundOverlap
+            );
+        }
+        let round = &mut ctx.accounts.round;
+
+        round.index = presale.total_rounds;
+        round.start_time = start_time;
+        round.end_time = end_time;
+        round.token_allocation = token_allocation;
+        round.price_per_token_in_sol = price_per_token_in_sol;
+        round.price_per_token_in_usdc = price_per_token_in_usdc;
+
+        presale.total_rounds = presale
+            .total_rounds
+            .checked_add(1)
+            .ok_or(CustomError::TooManyRounds)?;
+        Ok(())
+    }
+
+    // edits a round that has not started selling yet. the active round can't be repriced.
+    // the new window has to fit between `previous_round` and `next_round`. like create_round,
+    // the edit has to be made at least timelock_delay before the old and the new start time.
+    pub fn update_round(
+        ctx: Context<UpdateRound>,
+        start_time: u64,
+        end_time: u64,
+        token_allocation: u64,
+        price_per_token_in_sol: u64,
+        price_per_token_in_usdc: u64,
+    ) -> Result<()> {
+        require!(end_time > start_time, CustomError::WrongTime);
+        require!(token_allocation > 0, CustomError::ZeroAmount);
+        require!(
+            price_per_token_in_sol > 0 && price_per_token_in_usdc > 0,
+            CustomError::InvalidPrice
+        );
+
+        let presale = &ctx.accounts.presale;
+        let round = &mut ctx.accounts.round;
+
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        require!(
+            round.index >= presale.current_round
+                && round.tokens_sold == 0
+                && cur_timestamp < round.start_time,
+            CustomError::RoundAlreadyStarted
+        );
+        let notice_time = cur_timestamp.saturating_add(presale.timelock_delay);
+        require!(
+            round.start_time >= notice_time && start_time >= notice_time,
+            CustomError::RoundStartTooSoon
+        );
+        if round.index > 0 {
+            let previous_round = ctx
+                .accounts
+                .previous_round
+                .as_ref()
+                .ok_or(CustomError::RoundRequired)?;
+            require!(
+                start_time >= previous_round.end_time,
+                CustomError::RoundOverlap
+            );
+        }
+        if round.index + 1 < presale.total_rounds {
+            let next_round = ctx
+                .accounts
+                .next_round
+                .as_ref()
+                .ok_or(CustomError::RoundRequired)?;
+            require!(end_time <= next_round.start_time, CustomError::RoundOverlap);
+        }
+
+        round.start_time = start_time;
+        round.end_time = end_time;
+        round.token_allocation = token_allocation;
+        round.price_per_token_in_sol = price_per_token_in_sol;
+        round.price_per_token_in_usdc = price_per_token_in_usdc;
+        Ok(())
+    }
+
+    // caps of 0 are disabled. the soft cap is in tokens so sol and usdc sales count towards it alike.
+    // once tokens have been sold the soft cap is fixed, since it decides whether buyers get
+    // refunds, and the hard caps can't go below what has already been sold or raised.
+    pub fn set_caps(
+        ctx: Context<StopPresale>,
+        hard_cap_tokens: u64,
+        hard_cap_sol: u64,
+        hard_cap_usdc: u64,
+        soft_cap_tokens: u64,
+    ) -> Result<()> {
+        ctx.accounts.require_role(Role::SuperAdmin)?;
+        require!(
+            hard_cap_tokens == 0 || soft_cap_tokens <= hard_cap_tokens,
+            CustomError::InvalidCaps
+        );
+        let presale = &mut ctx.accounts.presale;
+        require!(!presale.is_finalized, CustomError::PresaleFinalized);
+        require!(
+            presale.total_tokens_sold == 0 || soft_cap_tokens == presale.soft_cap_tokens,
+            CustomError::SoftCapLocked
+        );
+        require!(
+            (hard_cap_tokens == 0 || hard_cap_tokens >= presale.committed_tokens())
+                && (hard_cap_sol == 0 || hard_cap_sol >= presale.sol_amount_raised)
+                && (hard_cap_usdc == 0 || hard_cap_usdc >= presale.usdc_amount_raised),
+            CustomError::CapBelowSold
+        );
+
+        presale.hard_cap_tokens = hard_cap_tokens;
+        presale.hard_cap_sol = hard_cap_sol;
+        presale.hard_cap_usdc = hard_cap_usdc;
+        presale.soft_cap_tokens = soft_cap_tokens;
+        Ok(())
+    }
+
+    pub fn toggle_presale(ctx: Context<StopPresale>, toggle: bool) -> Result<()> {
+        ctx.accounts.require_role(Role::Pauser)?;
+        let presale = &mut ctx.accounts.presale;
+        require!(
+            !(toggle && presale.is_finalized),
+            CustomError::PresaleFinalized
+        );
+
+        presale.is_live = toggle;
+        Ok(())
+    }
+
+    // extends or shortens the presale. the end time can't be moved once it has passed,
+    // and can't be set to a time that has already passed.
+    pub fn set_end_time(ctx: Context<StopPresale>, end_time: u64) -> Result<()> {
+        ctx.accounts.require_role(Role::SuperAdmin)?;
+        let presale = &mut ctx.accounts.presale;
+        require!(!presale.is_finalized, CustomError::PresaleFinalized);
+
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        require!(!presale.has_ended(cur_timestamp), CustomError::PresaleEnded);
+        require!(
+            end_time > cur_timestamp && end_time > presale.start_time,
+            CustomError::WrongTime
+        );
+
+        presale.end_time = end_time;
+        Ok(())
+    }
+
+    // closes the presale for good and records whether it reached its soft cap, once the end
+    // time has passed or a hard cap was reached. a failed presale lets investors claim refunds,
+    // sol and usdc can only be withdrawn after a successful one.
+    pub fn finalize_presale(ctx: Context<StopPresale>) -> Result<()> {
+        ctx.accounts.require_role(Role::SuperAdmin)?;
+        let presale = &mut ctx.accounts.presale;
+        require!(!presale.is_finalized, CustomError::PresaleFinalized);
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        require!(
+            presale.has_ended(cur_timestamp) || presale.hard_cap_reached(),
+            CustomError::PresaleNotEnded
+        );
+
+        presale.is_live = false;
+        presale.is_finalized = true;
+        presale.is_successful = presale.soft_cap_reached();
+        msg!("Presale successful: {}", presale.is_successful);
+        Ok(())
+    }
+
+    // switches invest between instant transfers and crediting tokens for claim_tokens.
+    // deferred delivery needs a TGE time in the future, and once set it can't be moved.
+    // the delivery mode can't be changed once TGE has passed.
+    pub fn set_token_delivery(
+        ctx: Context<StopPresale>,
+        deferred_delivery: bool,
+        tge_time: u64,
+    ) -> Result<()> {
+        ctx.accounts.require_role(Role::SuperAdmin)?;
+        let presale = &mut ctx.accounts.presale;
+
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        require!(
+            presale.tge_time == 0 || cur_timestamp < presale.tge_time,
+            CustomError::WrongTime
+        );
+        require!(
+            presale.tge_time == 0 || tge_time == presale.tge_time,
+            CustomError::TgeLocked
+        );
+        // 0 is only allowed for instant delivery, before a TGE time has been set
+        require!(
+            tge_time > cur_timestamp || (tge_time == 0 && !deferred_delivery),
+            CustomError::WrongTime
+        );
+
+        // vested tokens have to stay in the presale until they are claimed
+        require!(
+            deferred_delivery || !presale.has_vesting(),
+            CustomError::VestingEnabled
+        );
+
+        presale.deferred_delivery = deferred_delivery;
+        presale.tge_time = tge_time;
+        Ok(())
+    }
+
+    // sets the vesting schedule for tokens bought with invest and turns on deferred delivery.
+    // the schedule can't be changed once TGE has passed.
+    pub fn set_vesting(
+        ctx: Context<StopPresale>,
+        tge_unlock_bps: u16,
+        cliff_duration: u64,
+        vesting_duration: u64,
+    ) -> Result<()> {
+        ctx.accounts.require_role(Role::SuperAdmin)?;
+        require!(
+            u64::from(tge_unlock_bps) <= BPS_DENOMINATOR,
+            CustomError::InvalidVesting
+        );
+        let presale = &mut ctx.accounts.presale;
+
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        require!(
+            presale.tge_time == 0 || cur_timestamp < presale.tge_time,
+            CustomError::WrongTime
+        );
+
+        presale.tge_unlock_bps = tge_unlock_bps;
+        presale.cliff_duration = cliff_duration;
+        presale.vesting_duration = vesting_duration;
+        presale.deferred_delivery = true;
+        Ok(())
+    }
+
+    // replaces the usdc mint set at initialization, e.g. when a presale moves from a local
+    // stand-in to the cluster's usdc. usdc withdrawals are counted for the mint at the time.
+    pub fn set_usdc_mint(ctx: Context<SetUsdcMint>) -> Result<()> {
+        // like at registration, usdc can only be a usd pegged payment mint
+        let payment_mint = &ctx.accounts.payment_mint;
+        if !payment_mint.data_is_empty() {
+            let payment_mint =
+                PaymentMint::try_deserialize(&mut &payment_mint.try_borrow_data()?[..])?;
+            require!(payment_mint.usd_pegged, CustomError::InvalidUSDC);
+        }
+
+        ctx.accounts.presale.usdc_mint = ctx.accounts.usdc_mint.key();
+        Ok(())
+    }
+
+    // accepts a new spl mint as payment. usd pegged mints follow the usd price of the presale
+    // or the active round, other mints are converted at the usd price of their own `price_feed`.
+    // wrapped sol is priced like sol and counts towards the sol caps and limits.
+    pub fn add_payment_mint(
+        ctx: Context<AddPaymentMint>,
+        usd_pegged: bool,
+        price_feed: Pubkey,
+        min_investment: u64,
+        max_investment: u64,
+    ) -> Result<()> {
+        validate_payment_pricing(
+            &ctx.accounts.payment_token_mint.key(),
+            usd_pegged,
+            &price_feed,
+        )?;
+        require!(
+            max_investment == 0 || min_investment <= max_investment,
+            CustomError::WrongAmount
+        );
+        require!(
+            usd_pegged || ctx.accounts.payment_token_mint.key() != ctx.accounts.presale.usdc_mint,
+            CustomError::InvalidUSDC
+        );
+        require!(
+            ctx.accounts.payment_token_mint.key() != ctx.accounts.presale.token_mint,
+            CustomError::InvalidPaymentToken
+        );
+        let payment_mint = &mut ctx.accounts.payment_mint;
+
+        payment_mint.payment_token_mint = ctx.accounts.payment_token_mint.key();
+        payment_mint.decimals = ctx.accounts.payment_token_mint.decimals;
+        payment_mint.usd_pegged = usd_pegged;
+        payment_mint.price_feed = price_feed;
+        payment_mint.min_investment = min_investment;
+        payment_mint.max_investment = max_investment;
+        payment_mint.is_active = true;
+        Ok(())
+    }
+
+    // reprices a payment mint or disables it for new purchases with a queued
+    // AdminAction::UpdatePaymentMint. refunds of a disabled mint still work.
+    pub fn update_payment_mint(ctx: Context<UpdatePaymentMint>) -> Result<()> {
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        let timelock_action = &mut ctx.accounts.timelock_action;
+        timelock_action.execute(cur_timestamp)?;
+        let AdminAction::UpdatePaymentMint {
+            mint,
+            usd_pegged,
+            price_feed,
+            min_investment,
+            max_investment,
+            is_active,
+        } = timelock_action.action
+        else {
+            return err!(CustomError::ActionMismatch);
+        };
+        require!(
+            mint == ctx.accounts.payment_mint.payment_token_mint,
+            CustomError::ActionMismatch
+        );
+        // the usdc mint can have changed since the action was queued
+        require!(
+            usd_pegged || mint != ctx.accounts.presale.usdc_mint,
+            CustomError::InvalidUSDC
+        );
+        let payment_mint = &mut ctx.accounts.payment_mint;
+
+        payment_mint.usd_pegged = usd_pegged;
+        payment_mint.price_feed = price_feed;
+        payment_mint.min_investment = min_investment;
+        payment_mint.max_investment = max_investment;
+        payment_mint.is_active = is_active;
+        Ok(())
+    }
+
+    // min limits apply per purchase, max limits to the cumulative investment of a wallet
+    pub fn set_investment_limits(
+        ctx: Context<StopPresale>,
+        min_sol_investment: u64,
+        max_sol_investment: u64,
+        min_usdc_investment: u64,
+        max_usdc_investment: u64,
+    ) -> Result<()> {
+        ctx.accounts.require_role(Role::SuperAdmin)?;
+        require!(
+            max_sol_investment == 0 || min_sol_investment <= max_sol_investment,
+            CustomError::WrongAmount
+        );
+        require!(
+            max_usdc_investment == 0 || min_usdc_investment <= max_usdc_investment,
+            CustomError::WrongAmount
+        );
+        let presale = &mut ctx.accounts.presale;
+
+        presale.min_sol_investment = min_sol_investment;
+        presale.max_sol_investment = max_sol_investment;
+        presale.min_usdc_investment = min_usdc_investment;
+        presale.max_usdc_investment = max_usdc_investment;
+        Ok(())
+    }
+
+    // rotates the whitelist merkle root. an all zero root turns the whitelist phase off.
+    pub fn set_whitelist(
+        ctx: Context<StopPresale>,
+        merkle_root: [u8; 32],
+        whitelist_end_time: u64,
+    ) -> Result<()> {
+        ctx.accounts.require_role(Role::SuperAdmin)?;
+        let presale = &mut ctx.accounts.presale;
+
+        presale.merkle_root = merkle_root;
+        presale.whitelist_end_time = whitelist_end_time;
+        Ok(())
+    }
+
+    // bonus tokens credited to the referrer of a purchase, relative to the tokens bought.
+    // only applies to purchases made after the change.
+    pub fn set_referral_bonus(ctx: Context<StopPresale>, referral_bonus_bps: u16) -> Result<()> {
+        ctx.accounts.require_role(Role::SuperAdmin)?;
+        require!(
+            u64::from(referral_bonus_bps) <= BPS_DENOMINATOR,
+            CustomError::WrongAmount
+        );
+        let presale = &mut ctx.accounts.presale;
+
+        presale.referral_bonus_bps = referral_bonus_bps;
+        Ok(())
+    }
+
+    // replaces the bonus tier table. volume tiers must be sorted by min_tokens,
+    // only the highest tier a purchase reaches applies.
+    pub fn set_bonus_tiers(
+        ctx: Context<SetBonusTiers>,
+        tiers: Vec<BonusTier>,
+        early_bonus_bps: u16,
+        early_bonus_duration: u64,
+    ) -> Result<()> {
+        require!(
+            tiers.len() <= MAX_BONUS_TIERS,
+            CustomError::InvalidBonusTiers
+        );
+        require!(
+            tiers
+                .windows(2)
+                .all(|pair| pair[0].min_tokens < pair[1].min_tokens),
+            CustomError::InvalidBonusTiers
+        );
+        require!(
+            tiers
+                .iter()
+                .all(|tier| u64::from(tier.bonus_bps) <= BPS_DENOMINATOR)
+                && u64::from(early_bonus_bps) <= BPS_DENOMINATOR,
+            CustomError::InvalidBonusTiers
+        );
+        let bonus_config = &mut ctx.accounts.bonus_config;
+
+        bonus_config.tier_count = tiers.len() as u8;
+        bonus_config.tiers = [BonusTier::default(); MAX_BONUS_TIERS];
+        bonus_config.tiers[..tiers.len()].copy_from_slice(&tiers);
+        bonus_config.early_bonus_bps = early_bonus_bps;
+        bonus_config.early_bonus_duration = early_bonus_duration;
+        Ok(())
+    }
+
+    // second step of an authority transfer queued as AdminAction::ProposeAuthority.
+    // signed by the pending authority, moves presale and staking control to it
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let staking = &mut ctx.accounts.staking;
+
+        presale.authority = presale.pending_authority;
+        staking.authority = presale.pending_authority;
+        presale.pending_authority = Pubkey::default();
+        Ok(())
+    }
+
+    pub fn cancel_authority_transfer(ctx: Context<StopPresale>) -> Result<()> {
+        ctx.accounts.require_role(Role::SuperAdmin)?;
+        let presale = &mut ctx.accounts.presale;
+        require!(
+            presale.pending_authority != Pubkey::default(),
+            CustomError::NoPendingAuthority
+        );
+
+        presale.pending_authority = Pubkey::default();
+        Ok(())
+    }
+
+    // gives `role` to `holder`, replacing its previous holder
+    pub fn grant_role(ctx: Context<ManageRoles>, role: Role, holder: Pubkey) -> Result<()> {
+        let roles = &mut ctx.accounts.roles;
+
+        *roles.holder_mut(role)? = holder;
+        Ok(())
+    }
+
+    pub fn revoke_role(ctx: Context<ManageRoles>, role: Role) -> Result<()> {
+        let roles = &mut ctx.accounts.roles;
+
+        *roles.holder_mut(role)? = Pubkey::default();
+        Ok(())
+    }
+
+    // queues an admin action that can be executed once the timelock delay has passed.
+    // the signer needs the role of the action, queued actions can be read by anyone.
+    pub fn queue_action(ctx: Context<QueueAction>, action: AdminAction) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        require!(
+            has_role(
+                ctx.accounts.roles.as_deref(),
+                &presale.authority,
+                &ctx.accounts.signer.key(),
+                action.role()
+            ),
+            CustomError::Unauthorized
+        );
+        if let AdminAction::SetTimelockDelay { delay } = action {
+            require!(
+                (MIN_TIMELOCK_DELAY..=MAX_TIMELOCK_DELAY).contains(&delay),
+                CustomError::WrongTime
+            );
+        }
+        if let AdminAction::SetApprovers {
+            approvers,
+            approver_count,
+            threshold,
+        } = action
+        {
+            require!(
+                approver_count as usize <= MAX_APPROVERS,
+                CustomError::InvalidApprovers
+            );
+            validate_approvers(&approvers[..approver_count as usize], threshold)?;
+        }
+        if let AdminAction::SetPriceFeed {
+            max_confidence_bps, ..
+        } = action
+        {
+            require!(
+                u64::from(max_confidence_bps) <= BPS_DENOMINATOR,
+                CustomError::InvalidPriceFeed
+            );
+        }
+        if let AdminAction::UpdatePaymentMint {
+            mint,
+            usd_pegged,
+            price_feed,
+            min_investment,
+            max_investment,
+            ..
+        } = action
+        {
+            validate_payment_pricing(&mint, usd_pegged, &price_feed)?;
+            require!(
+                max_investment == 0 || min_investment <= max_investment,
+                CustomError::WrongAmount
+            );
+        }
+        // the flat prices are only used while no presale rounds exist
+        if let AdminAction::ChangePrice { .. } = action {
+            require!(presale.total_rounds == 0, CustomError::RoundPricesActive);
+        }
+
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        let timelock_action = &mut ctx.accounts.timelock_action;
+
+        timelock_action.id = presale.action_count;
+        timelock_action.action = action;
+        timelock_action.proposer = ctx.accounts.signer.key();
+        timelock_action.queued_at = cur_timestamp;
+        timelock_action.eta = cur_timestamp + presale.timelock_delay;
+        presale.action_count += 1;
+        msg!(
+            "Action {} executable at {}",
+            timelock_action.id,
+            timelock_action.eta
+        );
+        Ok(())
+    }
+
+    // runs a queued action once its eta has passed. the signer needs the role of the action.
+    pub fn execute_action(ctx: Context<ExecuteAction>) -> Result<()> {
+        let timelock_action = &mut ctx.accounts.timelock_action;
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        timelock_action.execute(cur_timestamp)?;
+        require!(
+            has_role(
+                ctx.accounts.roles.as_deref(),
+                &ctx.accounts.presale.authority,
+                &ctx.accounts.signer.key(),
+                timelock_action.action.role()
+            ),
+            CustomError::Unauthorized
+        );
+
+        let presale = &mut ctx.accounts.presale;
+        match ctx.accounts.timelock_action.action {
+            AdminAction::ChangePrice {
+                sol_price,
+                usdc_price,
+            } => {
+                require!(presale.total_rounds == 0, CustomError::RoundPricesActive);
+                presale.price_per_token_in_sol = sol_price;
+                presale.price_per_token_in_usdc = usdc_price;
+            }
+            AdminAction::ProposeAuthority { authority } => {
+                // the new authority has to accept it, see accept_authority
+                presale.pending_authority = authority;
+            }
+            AdminAction::SetTimelockDelay { delay } => {
+                presale.timelock_delay = delay;
+            }
+            AdminAction::SetPriceFeed {
+                price_feed,
+                max_price_age,
+                max_confidence_bps,
+            } => {
+                presale.price_feed = price_feed;
+                presale.max_price_age = max_price_age;
+                presale.max_confidence_bps = max_confidence_bps;
+            }
+            // these change other accounts and run through their own instructions
+            AdminAction::SetApprovers { .. }
+            | AdminAction::AddTreasuryWallet { .. }
+            | AdminAction::RemoveTreasuryWallet { .. }
+            | AdminAction::UpdatePaymentMint { .. } => {
+                return err!(CustomError::ActionMismatch);
+            }
+            AdminAction::WithdrawStakingTokens { destination } => {
+                // withdraws the tokens in staking that aren't staked or owed as rewards
+                // to the allowlisted treasury wallet queued with the action
+                require!(
+                    optional_key(ctx.accounts.destination.as_ref()) == destination
+                        && ctx.accounts.treasury_wallet.is_some(),
+                    CustomError::Unauthorized
+                );
+                let staking_token_account = ctx
+                    .accounts
+                    .staking_token_account
+                    .as_ref()
+                    .ok_or(CustomError::InvalidToken)?;
+                let destination_token_account = ctx
+                    .accounts
+                    .destination_token_account
+                    .as_ref()
+                    .ok_or(CustomError::InvalidToken)?;
+                let reward_schedule = ctx
+                    .accounts
+                    .reward_schedule
+                    .as_ref()
+                    .ok_or(CustomError::InvalidRewardSchedule)?;
+                let staking = &mut ctx.accounts.staking;
+                staking.update_rewards(reward_schedule, cur_timestamp)?;
+                let amount = staking_token_account
+                    .amount
+                    .saturating_sub(staking.reserved_tokens());
+                if amount > 0 {
+                    transfer(
+                        CpiContext::new_with_signer(
+                            ctx.accounts.token_program.to_account_info(),
+                            Transfer {
+                                from: staking_token_account.to_account_info(),
+                                to: destination_token_account.to_account_info(),
+                                authority: staking.to_account_info(),
+                            },
+                            &[&[STAKING_SEED, &[ctx.bumps.staking]][..]],
+                        ),
+                        amount,
+                    )?;
+                    presale.tokens_withdrawn += amount;
+                    presale.last_withdrawal_time = cur_timestamp;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // drops a queued action. the super admin or a holder of the action's role can cancel it.
+    pub fn cancel_action(ctx: Context<CancelAction>) -> Result<()> {
+        let timelock_action = &mut ctx.accounts.timelock_action;
+        require!(
+            !timelock_action.executed && !timelock_action.cancelled,
+            CustomError::ActionNotPending
+        );
+        require!(
+            has_role(
+                ctx.accounts.roles.as_deref(),
+                &ctx.accounts.presale.authority,
+                &ctx.accounts.signer.key(),
+                timelock_action.action.role()
+            ),
+            CustomError::Unauthorized
+        );
+
+        timelock_action.cancelled = true;
+        Ok(())
+    }
+
+    // pulls out the remaining tokens once the presale has ended. sends `amount` to the token
+    // account of an allowlisted treasury wallet, the tokens owed to buyers and referrers stay.
+    pub fn admin_withdraw_tokens(ctx: Context<AdminWithdrawTokens>, amount: u64) -> Result<()> {
+        require!(amount > 0, CustomError::ZeroAmount);
+        let presale = &ctx.accounts.presale;
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        require!(
+            presale.is_finalized || presale.has_ended(cur_timestamp),
+            CustomError::PresaleNotEnded
+        );
+        let withdrawable = ctx
+            .accounts
+            .presale_token_account
+            .amount
+            .saturating_sub(presale.owed_tokens());
+        require!(amount <= withdrawable, CustomError::InsufficientFunds);
+
+        transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.presale_token_account.to_account_info(),
+                    to: ctx.accounts.destination_token_account.to_account_info(),
+                    authority: ctx.accounts.presale.to_account_info(),
+                },
+                &[&[PRESALE_SEED, &[ctx.bumps.presale]][..]],
+            ),
+            amount,
+        )?;
+
+        let presale = &mut ctx.accounts.presale;
+        presale.tokens_withdrawn += amount;
+        presale.last_withdrawal_time = cur_timestamp;
+        Ok(())
+    }
+
+    ////////////////////////////////////////////////////////////
+    //                        Multisig Treasury
+    ////////////////////////////////////////////////////////////
+
+    // the approvers and the treasury wallets are changed through queued admin actions,
+    // the instructions below execute them once the timelock delay has passed.
+
+    // allows `wallet` as destination of treasury withdrawals, see AdminAction::AddTreasuryWallet
+    pub fn add_treasury_wallet(ctx: Context<AddTreasuryWallet>, wallet: Pubkey) -> Result<()> {
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        let timelock_action = &mut ctx.accounts.timelock_action;
+        timelock_action.execute(cur_timestamp)?;
+        require!(
+            timelock_action.action == AdminAction::AddTreasuryWallet { wallet },
+            CustomError::ActionMismatch
+        );
+
+        ctx.accounts.treasury_wallet.wallet = wallet;
+        Ok(())
+    }
+
+    // closes the allowlist entry, open proposals to the wallet can't execute anymore.
+    // see AdminAction::RemoveTreasuryWallet
+    pub fn remove_treasury_wallet(ctx: Context<RemoveTreasuryWallet>) -> Result<()> {
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        let timelock_action = &mut ctx.accounts.timelock_action;
+        timelock_action.execute(cur_timestamp)?;
+        require!(
+            timelock_action.action
+                == AdminAction::RemoveTreasuryWallet {
+                    wallet: ctx.accounts.treasury_wallet.wallet
+                },
+            CustomError::ActionMismatch
+        );
+        Ok(())
+    }
+
+    // replaces the approver set with the one of a queued AdminAction::SetApprovers.
+    // changing it invalidates every open withdrawal proposal.
+    pub fn set_approvers(ctx: Context<SetApprovers>) -> Result<()> {
+        let cur_timestamp = u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        let timelock_action = &mut ctx.accounts.timelock_action;
+        timelock_action.execute(cur_timestamp)?;
+        let AdminAction::SetApprovers {
+            approvers,
+            approver_count,
+            threshold,
+        } = timelock_action.action
+        else {
+            return err!(CustomError::ActionMismatch);
+        };
+
+        let count = approver_count as usize;
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.approvers = [Pubkey::default(); MAX_APPROVERS];
+        multisig.approvers[..count].copy_from_slice(&approvers[..count]);
+        multisig.approver_count = approver_count;
+        multisig.threshold = threshold;
+        multisig.version += 1;
+        Ok(())
+    }
+
+    // proposes to send `amount` of sol (payment_token_mint = default pubkey) or of a payment
+    // token held by the presale to the treasury wallet `destination`.
+    // the proposer's approval is counted.
+    pub fn propose_withdrawal(
+        ctx: Context<ProposeWithdrawal>,
+        payment_token_mint: Pubkey,
+        destination: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, CustomError::ZeroAmount);
+        // sol, the usdc mint or a registered payment mint. the sale token goes through
+        // admin_withdraw_tokens, which keeps the tokens owed to buyers.
+        require!(
+            payment_token_mint == Pubkey::default()
+                || payment_token_mint == ctx.accounts.presale.usdc_mint
+                || ctx.accounts.payment_mint.is_some(),
+            CustomError::InvalidPaymentToken
+        );
+        let multisig = &mut ctx.accounts.multisig;
+        let index = multisig
+            .approver_index(&ctx.accounts.signer.key())
+            .ok_or(CustomError::NotApprover)?;
+        let proposal = &mut ctx.accounts.proposal;
+
+        proposal.id = multisig.proposal_count;
+        proposal.proposer = ctx.accounts.signer.key();
+        proposal.payment_token_mint = payment_token_mint;
+        proposal.destination = destination;
+        proposal.amount = amount;
+        proposal.approvals[index] = true;
+        proposal.approval_count = 1;
+        proposal.approvers_version = multisig.version;
+        multisig.proposal_count += 1;
+        msg!("Withdrawal proposal {}", proposal.id);
+        Ok(())
+    }
+
+    pub fn approve_withdrawal(ctx: Context<ApproveWithdrawal>) -> Result<()> {
+        let multisig = &ctx.accounts.multisig;
+        let proposal = &mut ctx.accounts.proposal;
+        require!(
+            !proposal.executed && proposal.approvers_version == multisig.version,
+            CustomError::ProposalNotPending
+        );
+        let index = multisig
+            .approver_index(&ctx.accounts.signer.key())
+            .ok_or(CustomError::NotApprover)?;
+        require!(!proposal.approvals[index], CustomError::AlreadyApproved);
+
+        proposal.approvals[index] = true;
+        proposal.approval_count += 1;
+        Ok(())
+    }
+
+    // sends the proposed withdrawal once `threshold` approvers signed off. any approver can execute.
+    pub fn execute_withdrawal(ctx: Context<ExecuteWithdrawal>) -> Result<()> {
+        // funds of a failed presale belong to the investors, and until the presale is
+        // finalized it isn't known whether it failed
+        require!(
+            !ctx.accounts.presale.is_failed(),
+            CustomError::PresaleFailed
+        );
+        require!(
+            ctx.accounts.presale.is_finalized,
+            CustomError::PresaleNotFinalized
+        );
+        let multisig = &ctx.accounts.multisig;
+        let proposal = &mut ctx.accounts.proposal;
+        require!(
+            !proposal.executed && proposal.approvers_version == multisig.version,
+            CustomError::ProposalNotPending
+        );
+        require!(
+            proposal.approval_count >= multisig.threshold,
+            CustomError::NotEnoughApprovals
+        );
+        proposal.executed = true;
+        let amount = proposal.amount;
+
+        if proposal.payment_token_mint == Pubkey::default() {
+            let presale = &mut ctx.accounts.presale.to_account_info();
+
+            // the presale account has to stay rent exempt
+            let rent_exemption = Rent::get()?.minimum_balance(presale.data_len());
+            require!(
+                presale.lamports() >= rent_exemption + amount,
+                CustomError::InsufficientFunds
+            );
+
+            **presale.try_borrow_mut_lamports()? -= amount;
+            **ctx.accounts.destination.try_borrow_mut_lamports()? += amount;
+            ctx.accounts.presale.sol_withdrawn += amount;
+        } else {
+            let presale_payment_account = ctx
+                .accounts
+                .presale_payment_account
+                .as_ref()
+                .ok_or(CustomError::InvalidPaymentToken)?;
+            let destination_token_account = ctx
+                .accounts
+                .destination_token_account
+                .as_ref()
+                .ok_or(CustomError::InvalidPaymentToken)?;
+            require!(
+                ctx.accounts.payment_token_mint.is_some()
+                    && proposal.payment_token_mint != ctx.accounts.presale.token_mint,
+                CustomError::InvalidPaymentToken
+            );
+            require!(
+                presale_payment_account.amount >= amount,
+                CustomError::InsufficientFunds
+            );
+
+            transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: presale_payment_account.to_account_info(),
+                        to: destination_token_account.to_account_info(),
+                        authority: ctx.accounts.presale.to_account_info(),
+                    },
+                    &[&[PRESALE_SEED, &[ctx.bumps.presale]]],
+                ),
+                amount,
+            )?;
+            if proposal.payment_token_mint == ctx.accounts.presale.usdc_mint {
+                ctx.accounts.presale.usdc_withdrawn += amount;
+            }
+        }
+        ctx.accounts.presale.last_withdrawal_time =
+            u64::try_from(Clock::get()?.unix_timestamp).unwrap();
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////
+//                        Constants
+////////////////////////////////////////////////////////////
+pub const PRESALE_SEED: &[u8] = "solana_presale".as_bytes();
+pub const DATA_SEED: &[u8] = "my_data".as_bytes();
+pub const STAKING_SEED: &[u8] = "solana_staking".as_bytes();
+pub const STAKING_DATA_SEED: &[u8] = "staking_user_data".as_bytes();
+pub const ROUND_SEED: &[u8] = "presale_round".as_bytes();
+pub const PAYMENT_MINT_SEED: &[u8] = "payment_mint".as_bytes();
+pub const PAYMENT_DATA_SEED: &[u8] = "payment_data".as_bytes();
+pub const REFERRAL_SEED: &[u8] = "referral".as_bytes();
+pub const BONUS_SEED: &[u8] = "bonus_config".as_bytes();
+pub const ROLES_SEED: &[u8] = "roles".as_bytes();
+pub const ACTION_SEED: &[u8] = "timelock_action".as_bytes();
+pub const MULTISIG_SEED: &[u8] = "multisig".as_bytes();
+pub const WITHDRAWAL_SEED: &[u8] = "withdrawal_proposal".as_bytes();
+pub const TREASURY_WALLET_SEED: &[u8] = "treasury_wallet".as_bytes();
+pub const REWARD_SCHEDULE_SEED: &[u8] = "reward_schedule".as_bytes();
+pub const PRECISION: u64 = 100000; // Match token decimals = 5
+pub const REWARD_PRECISION: u128 = 1_000_000_000_000; // scale of acc_reward_per_token
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const USDC_DECIMALS: u8 = 6;
+pub const SOL_DECIMALS: u8 = 9;
+pub const MAX_BONUS_TIERS: usize = 5;
+pub const MIN_TIMELOCK_DELAY: u64 = 24 * 60 * 60; // 1 day
+pub const MAX_TIMELOCK_DELAY: u64 = 30 * 24 * 60 * 60; // 30 days
+pub const DEFAULT_TIMELOCK_DELAY: u64 = 2 * 24 * 60 * 60; // 2 days, until a SetTimelockDelay action changes it
+pub const MAX_REWARD_PERIOD_DURATION: u64 = 365 * 24 * 60 * 60; // 1 year
+pub const MAX_APPROVERS: usize = 10;
+// daily rewards of the older version, one per 30 day month since the staking start
+pub const LEGACY_DAILY_REWARDS: [u64; 12] = [
+    1205350000, 1237979000, 1270512000, 1303141000, 1335674000, 1368302000, 1400836000, 1433369000,
+    1465998000, 1498531000, 1531159000, 1563693000,
+];
+pub const MONTH_DURATION: u64 = (30 * 24 * 60 * 60) * PRECISION;
 pub const DAY_DURATION: u64 = (24 * 60 * 60) * PRECISION;
+// pyth v2 price account layout
+pub const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+pub const PYTH_PRICE_ACCOUNT_TYPE: u32 = 3;
+pub const PYTH_STATUS_TRADING: u32 = 1;
+pub const PYTH_ACCOUNT_LEN: usize = 240;
+// pyth oracle programs owning the price accounts on mainnet and devnet
+pub const PYTH_ORACLE_PROGRAMS: [Pubkey; 2] = [
+    pubkey!("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH"),
+    pubkey!("gSbePebfvPy7tRqimPoVecS2UsBvYv46ynrzWocc92s"),
+];
+pub const MIN_SOL_INVESTMENT: u64 = 500_000_000; // 0.5 sol
+pub const MAX_SOL_INVESTMENT: u64 = 200_000_000_000; // 200 sol
+pub const MIN_USDC_INVESTMENT: u64 = 100_000_000; // 100 usdc
+pub const MAX_USDC_INVESTMENT: u64 = 40_000_000_000; // 40,000 usdc
+pub const MAX_PRICE_AGE: u64 = 60; // seconds, until a SetPriceFeed action sets it
+pub const MAX_CONFIDENCE_BPS: u16 = 100; // 1%, until a SetPriceFeed action sets it
+
+////////////////////////////////////////////////////////////
+//                        Account States
+////////////////////////////////////////////////////////////
+
+#[account]
+#[derive(Default)]
+pub struct StakingInfo {
+    pub token_mint: Pubkey,
+    pub authority: Pubkey, // follows the presale authority, admin checks use the presale one
+    pub total_tokens_staked: u64,
+    pub total_tokens_rewarded: u64,
+    pub staking_start_date: u64,
+    pub allow_claiming: bool,
+    pub acc_reward_per_token: u128, // rewards earned per staked token so far, scaled by REWARD_PRECISION
+    pub last_reward_time: u64,      // acc_reward_per_token includes the rewards until this time
+    pub rewards_accrued: u64,       // rewards emitted to stakers so far, paid out or not
+    pub legacy_end_time: u64,       // the older version's rewards count until migrate_staking
+    pub legacy_total_staked: u64,   // total_tokens_staked at migrate_staking
+    pub legacy_unmigrated_stake: u64, // stake of the positions migrate_staking_data didn't migrate yet
+    pub legacy_rewards_reserved: u64, // part of rewards_accrued kept for those positions
+}
+
+impl StakingInfo {
+    // adds the rewards emitted since last_reward_time to acc_reward_per_token.
+    // has to run before any staked balance changes.
+    pub fn update_rewards(&mut self, schedule: &RewardSchedule, cur_timestamp: u64) -> Result<()> {
+        if cur_timestamp <= self.last_reward_time {
+            return Ok(());
+        }
+        // nothing is emitted while the pool is empty
+        if self.total_tokens_staked > 0 {
+            let rewards = schedule.emitted_rewards(self.last_reward_time, cur_timestamp)?;
+            self.acc_reward_per_token = self
+                .acc_reward_per_token
+                .checked_add(rewards as u128 * REWARD_PRECISION / self.total_tokens_staked as u128)
+                .ok_or(CustomError::Overflow)?;
+            self.rewards_accrued = self
+                .rewards_accrued
+                .checked_add(rewards)
+                .ok_or(CustomError::Overflow)?;
+        }
+        self.last_reward_time = cur_timestamp;
+        Ok(())
+    }
+
+    // tokens the staking token account has to keep: the stakes and the rewards accrued to
+    // them that weren't paid out yet. compounded rewards are part of the stakes.
+    pub fn reserved_tokens(&self) -> u64 {
+        self.total_tokens_staked.saturating_add(
+            self.rewards_accrued
+                .saturating_sub(self.total_tokens_rewarded),
+        )
+    }
+}
+
+// staking emissions, period i runs from start_time + i * period_duration for period_duration
+// seconds and emits emissions[i] evenly over that time. periods can only be appended.
+#[account]
+#[derive(Default)]
+pub struct RewardSchedule {
+    pub start_time: u64,
+    pub period_duration: u64,
+    pub emissions: Vec<u64>,
+}
+
+impl RewardSchedule {
+    // account size with `periods` emissions
+    pub fn space(periods: usize) -> usize {
+        /*
+        Discriminator: 8 bytes
+        start_time, period_duration: 8 bytes each
+        emissions: 4 bytes length + 8 bytes per period
+         */
+        8 + 8 + 8 + 4 + 8 * periods
+    }
+
+    // end of the period starting at `period`, saturates for schedules past the end of time
+    fn period_end(&self, period: u64) -> u64 {
+        self.period_duration
+            .saturating_mul(period)
+            .saturating_add(self.start_time)
+    }
+
+    pub fn end_time(&self) -> u64 {
+        self.period_end(self.emissions.len() as u64)
+    }
+
+    // rewards the pool emits between `from` and `to`
+    pub fn emitted_rewards(&self, from: u64, to: u64) -> Result<u64> {
+        let mut rewards: u128 = 0;
+        let mut time = std::cmp::max(from, self.start_time);
+        let to = std::cmp::min(to, self.end_time());
+        while time < to {
+            let period = (time - self.start_time) / self.period_duration;
+            let end = std::cmp::min(to, self.period_end(period + 1));
+            rewards += self.emissions[period as usize] as u128 * (end - time) as u128
+                / self.period_duration as u128;
+            time = end;
+        }
+        Ok(u64::try_from(rewards).map_err(|_| CustomError::Overflow)?)
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct StakingData {
+    pub total_staking_balance: u64,
+    pub stake_date: u64,
+    pub is_first_time: bool,
+    pub last_claim_time: u64,
+    pub rewards_claimed: u64,
+    pub reward_debt: u128,    // share of acc_reward_per_token already settled
+    pub pending_rewards: u64, // settled rewards not claimed yet
+    pub auto_compound: bool,  // anyone can restake the rewards with compound
+    pub presale_tokens: u64,  // tokens bought with buy_and_stake, they unlock like invest purchases
+}
+
+impl StakingData {
+    fn accrued(&self, staking: &StakingInfo) -> Result<u128> {
+        Ok((self.total_staking_balance as u128)
+            .checked_mul(staking.acc_reward_per_token)
+            .ok_or(CustomError::Overflow)?
+            / REWARD_PRECISION)
+    }
+
+    // moves the rewards earned since the last settlement to pending_rewards.
+    // call after update_rewards and before changing the balance.
+    pub fn settle_rewards(&mut self, staking: &StakingInfo) -> Result<()> {
+        let accrued = self.accrued(staking)?;
+        let earned = accrued.saturating_sub(self.reward_debt);
+        self.pending_rewards = self
+            .pending_rewards
+            .checked_add(u64::try_from(earned).map_err(|_| CustomError::Overflow)?)
+            .ok_or(CustomError::Overflow)?;
+        self.reward_debt = accrued;
+        Ok(())
+    }
+
+    // call after changing the balance
+    pub fn sync_reward_debt(&mut self, staking: &StakingInfo) -> Result<()> {
+        self.reward_debt = self.accrued(staking)?;
+        Ok(())
+    }
+
+    // presale tokens that can't be unstaked yet. with deferred delivery they unlock at TGE
+    // and on the vesting schedule, the same as tokens bought with invest.
+    pub fn locked_tokens(&self, presale: &PresaleInfo, cur_timestamp: u64) -> u64 {
+        if !presale.deferred_delivery {
+            return 0;
+        }
+        self.presale_tokens - presale.vested_tokens(self.presale_tokens, cur_timestamp)
+    }
+
+    // takes the pending rewards for payout
+    pub fn claim_pending(&mut self, cur_timestamp: u64) -> Result<u64> {
+        let rewards = self.pending_rewards;
+        self.pending_rewards = 0;
+        self.last_claim_time = cur_timestamp;
+        self.rewards_claimed = self
+            .rewards_claimed
+            .checked_add(rewards)
+            .ok_or(CustomError::Overflow)?;
+        Ok(rewards)
+    }
+}
+
+// Account States
+#[account]
+#[derive(Default)]
+pub struct PresaleInfo {
+    pub token_mint: Pubkey,
+    pub sol_amount_raised: u64,  // total sol raised
+    pub usdc_amount_raised: u64, // total usd raised through payment mints, 6 decimals
+    pub total_tokens_sold: u64,  // total token sold
+    pub start_time: u64,
+    pub price_per_token_in_sol: u64,  // price per token in sol
+    pub price_per_token_in_usdc: u64, // price per token in sol
+    pub is_live: bool,                // is presale is live
+    pub is_initialized: bool,         // is presale is initialized
+    pub authority: Pubkey,
+    pub current_round: u8,    // index of the round purchases are priced against
+    pub total_rounds: u8,     // number of rounds created, 0 means the flat prices above are used
+    pub hard_cap_tokens: u64, // max tokens sold, given as bonus or owed to referrers, 0 means no cap
+    pub hard_cap_sol: u64,    // max lamports that can be raised, 0 means no cap
+    pub hard_cap_usdc: u64,   // max usd that can be raised through pegged mints, 0 means no cap
+    pub soft_cap_tokens: u64, // tokens that must be sold for the presale to succeed
+    pub is_finalized: bool,   // presale is closed for good
+    pub is_successful: bool,  // soft cap was reached when the presale was finalized
+    pub deferred_delivery: bool, // invest credits tokens instead of transferring them
+    pub tge_time: u64,        // credited tokens can be claimed from this time, 0 means not set
+    pub tge_unlock_bps: u16,  // share of the credited tokens unlocked at TGE, in basis points
+    pub cliff_duration: u64,  // seconds after TGE before linear vesting starts
+    pub vesting_duration: u64, // seconds over which the remaining tokens vest linearly
+    pub merkle_root: [u8; 32], // root of the whitelist tree, all zeroes means no whitelist
+    pub whitelist_end_time: u64, // purchases need a whitelist proof until this time
+    pub min_sol_investment: u64, // min sol per purchase
+    pub max_sol_investment: u64, // max sol per wallet, 0 means no limit
+    pub min_usdc_investment: u64, // min usd per purchase with a pegged mint
+    pub max_usdc_investment: u64, // max usd per wallet, 0 means no limit
+    pub price_feed: Pubkey,   // sol/usd price account, default pubkey means fixed sol prices
+    pub max_price_age: u64,   // max seconds since the last price update
+    pub max_confidence_bps: u16, // max confidence interval relative to the price
+    pub usdc_mint: Pubkey,    // usdc mint of the cluster, set at initialization or by set_usdc_mint
+    pub end_time: u64,        // purchases are rejected from this time on, 0 means no end
+    pub referral_bonus_bps: u16, // referrer bonus relative to the referred tokens
+    pub referral_rewards: u64, // bonus tokens owed to referrers
+    pub total_bonus_tokens: u64, // tokens given on top of total_tokens_sold by the bonus tiers
+    pub pending_authority: Pubkey, // proposed authority, default pubkey when no transfer is pending
+    pub timelock_delay: u64,  // seconds between queueing and executing an admin action
+    pub action_count: u64,    // id of the next queued action
+    pub sol_withdrawn: u64,   // lamports sent out by treasury withdrawals
+    pub usdc_withdrawn: u64,  // usdc sent out by treasury withdrawals
+    pub tokens_withdrawn: u64, // presale and staking tokens sent out by admin withdrawals
+    pub last_withdrawal_time: u64,
+    pub tokens_delivered: u64, // sold, bonus and referral tokens sent out to buyers and referrers
+}
+
+impl PresaleInfo {
+    // defaults of the settings, fills in the ones that are still unset
+    pub fn apply_defaults(&mut self) {
+        if self.min_sol_investment == 0 && self.max_sol_investment == 0 {
+            self.min_sol_investment = MIN_SOL_INVESTMENT;
+            self.max_sol_investment = MAX_SOL_INVESTMENT;
+        }
+        if self.min_usdc_investment == 0 && self.max_usdc_investment == 0 {
+            self.min_usdc_investment = MIN_USDC_INVESTMENT;
+            self.max_usdc_investment = MAX_USDC_INVESTMENT;
+        }
+        // a zero age or confidence rejects nearly every price, so zero means unset
+        if self.max_price_age == 0 {
+            self.max_price_age = MAX_PRICE_AGE;
+        }
+        if self.max_confidence_bps == 0 {
+            self.max_confidence_bps = MAX_CONFIDENCE_BPS;
+        }
+        // zero would let timelocked actions run right after they are queued
+        if self.timelock_delay == 0 {
+            self.timelock_delay = DEFAULT_TIMELOCK_DELAY;
+        }
+    }
+
+    // sold tokens with the bonus and referral rewards given on them, all count against the token cap
+    pub fn committed_tokens(&self) -> u64 {
+        self.total_tokens_sold
+            .saturating_add(self.total_bonus_tokens)
+            .saturating_add(self.referral_rewards)
+    }
+
+    // committed tokens still held by the presale for buyers and referrers. buyers of a failed
+    // presale return their tokens for a refund, so nothing is owed then.
+    pub fn owed_tokens(&self) -> u64 {
+        if self.is_failed() {
+            return 0;
+        }
+        self.committed_tokens()
+            .saturating_sub(self.tokens_delivered)
+    }
+
+    // tokens that can still be committed before the token hard cap is hit
+    pub fn available_tokens(&self) -> u64 {
+        if self.hard_cap_tokens > 0 {
+            self.hard_cap_tokens.saturating_sub(self.committed_tokens())
+        } else {
+            u64::MAX
+        }
+    }
+
+    pub fn has_ended(&self, cur_timestamp: u64) -> bool {
+        self.end_time > 0 && cur_timestamp >= self.end_time
+    }
+
+    pub fn oracle_enabled(&self) -> bool {
+        self.price_feed != Pubkey::default()
+    }
+
+    pub fn whitelist_active(&self, cur_timestamp: u64) -> bool {
+        self.merkle_root != [0; 32] && cur_timestamp < self.whitelist_end_time
+    }
+
+    pub fn has_vesting(&self) -> bool {
+        self.cliff_duration > 0 || self.vesting_duration > 0
+    }
+
+    // tokens out of `total` that are unlocked at `cur_timestamp`
+    pub fn vested_tokens(&self, total: u64, cur_timestamp: u64) -> u64 {
+        if self.tge_time == 0 || cur_timestamp < self.tge_time {
+            return 0;
+        }
+        let tge_unlock =
+            (total as u128 * self.tge_unlock_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+
+        let vesting_start = self.tge_time.saturating_add(self.cliff_duration);
+        if cur_timestamp < vesting_start {
+            return tge_unlock;
+        }
+        let elapsed = cur_timestamp - vesting_start;
+        if elapsed >= self.vesting_duration {
+            return total;
+        }
+        let linear = (total - tge_unlock) as u128 * elapsed as u128 / self.vesting_duration as u128;
+        tge_unlock + linear as u64
+    }
+
+    // investors can only get refunds once the presale is finalized below its soft cap
+    pub fn is_failed(&self) -> bool {
+        self.is_finalized && !self.is_successful
+    }
+
+    pub fn hard_cap_reached(&self) -> bool {
+        (self.hard_cap_tokens > 0 && self.committed_tokens() >= self.hard_cap_tokens)
+            || (self.hard_cap_sol > 0 && self.sol_amount_raised >= self.hard_cap_sol)
+            || (self.hard_cap_usdc > 0 && self.usdc_amount_raised >= self.hard_cap_usdc)
+    }
+
+    // the presale is considered successful once the soft cap is sold
+    pub fn soft_cap_reached(&self) -> bool {
+        self.total_tokens_sold >= self.soft_cap_tokens
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct PresaleRound {
+    pub index: u8,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub token_allocation: u64,        // tokens available in this round
+    pub tokens_sold: u64, // tokens sold in this round, bonus and referral rewards included
+    pub sol_amount_raised: u64, // sol raised in this round
+    pub usdc_amount_raised: u64, // usdc raised in this round
+    pub price_per_token_in_sol: u64, // price per token in sol
+    pub price_per_token_in_usdc: u64, // price per token in usdc
+}
+
+impl PresaleRound {
+    // a round is over once its allocation is sold out or its window has closed
+    pub fn is_over(&self, cur_timestamp: u64) -> bool {
+        self.tokens_sold >= self.token_allocation || cur_timestamp >= self.end_time
+    }
+
+    pub fn record_sale(&mut self, purchase: &Purchase) -> Result<()> {
+        let number_of_tokens = purchase
+            .number_of_tokens
+            .checked_add(purchase.bonus_tokens)
+            .and_then(|tokens| tokens.checked_add(purchase.referral_tokens))
+            .ok_or(CustomError::Overflow)?;
+        self.tokens_sold = self
+            .tokens_sold
+            .checked_add(number_of_tokens)
+            .ok_or(CustomError::Overflow)?;
+        require!(
+            self.tokens_sold <= self.token_allocation,
+            CustomError::RoundAllocationExceeded
+        );
+        self.sol_amount_raised = self
+            .sol_amount_raised
+            .checked_add(purchase.sol_value)
+            .ok_or(CustomError::Overflow)?;
+        self.usdc_amount_raised = self
+            .usdc_amount_raised
+            .checked_add(purchase.usd_value)
+            .ok_or(CustomError::Overflow)?;
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct InvestmentData {
+    pub sol_investment_amount: u64,
+    pub usdc_investment_amount: u64,
+    pub number_of_tokens: u64,
+    pub staked_tokens: u64,  // tokens bought through buy_and_stake
+    pub claimed_tokens: u64, // tokens bought through invest that were delivered
+    pub referrer: Pubkey,    // wallet that referred the first purchase, default pubkey if none
+    pub bonus_tokens: u64,   // part of number_of_tokens and staked_tokens given by the bonus tiers
+}
+
+impl InvestmentData {
+    pub fn has_purchased(&self) -> bool {
+        self.number_of_tokens > 0 || self.staked_tokens > 0
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct ReferralData {
+    pub referred_wallets: u64,
+    pub referred_sol_amount: u64,
+    pub referred_usdc_amount: u64,
+    pub referred_tokens: u64,
+    pub reward_tokens: u64,  // bonus tokens credited to the referrer
+    pub claimed_tokens: u64, // bonus tokens already claimed
+}
+
+// keys allowed to run parts of the admin functions. the presale authority is the
+// super admin, it grants or revokes the others and holds all but the treasurer and
+// staking manager roles, see has_role.
+#[account]
+#[derive(Default)]
+pub struct Roles {
+    pub price_manager: Pubkey,   // prices and rounds
+    pub pauser: Pubkey,          // toggle_presale
+    pub treasurer: Pubkey,       // withdrawals of unsold and staking tokens
+    pub staking_manager: Pubkey, // staking reward claims
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    SuperAdmin,
+    PriceManager,
+    Pauser,
+    Treasurer,
+    StakingManager,
+}
+
+impl Roles {
+    pub fn holder_mut(&mut self, role: Role) -> Result<&mut Pubkey> {
+        match role {
+            Role::SuperAdmin => err!(CustomError::InvalidRole),
+            Role::PriceManager => Ok(&mut self.price_manager),
+            Role::Pauser => Ok(&mut self.pauser),
+            Role::Treasurer => Ok(&mut self.treasurer),
+            Role::StakingManager => Ok(&mut self.staking_manager),
+        }
+    }
+
+    pub fn holder(&self, role: Role) -> Pubkey {
+        match role {
+            Role::SuperAdmin => Pubkey::default(),
+            Role::PriceManager => self.price_manager,
+            Role::Pauser => self.pauser,
+            Role::Treasurer => self.treasurer,
+            Role::StakingManager => self.staking_manager,
+        }
+    }
+}
+
+// admin action queued behind the timelock, seeded by its id
+#[account]
+pub struct TimelockAction {
+    pub id: u64,
+    pub action: AdminAction,
+    pub proposer: Pubkey,
+    pub queued_at: u64,
+    pub eta: u64, // executable from this time on
+    pub executed: bool,
+    pub cancelled: bool,
+}
+
+impl TimelockAction {
+    // marks the action executed, it has to be pending and past its eta
+    pub fn execute(&mut self, cur_timestamp: u64) -> Result<()> {
+        require!(
+            !self.executed && !self.cancelled,
+            CustomError::ActionNotPending
+        );
+        require!(cur_timestamp >= self.eta, CustomError::TimelockNotExpired);
+        self.executed = true;
+        Ok(())
+    }
+}
+
+// stored in fixed size TimelockAction accounts, so the size of the largest variant is paid anyway
+#[allow(clippy::large_enum_variant)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AdminAction {
+    ChangePrice {
+        sol_price: u64,
+        usdc_price: u64,
+    },
+    WithdrawStakingTokens {
+        destination: Pubkey, // allowlisted treasury wallet receiving the tokens
+    },
+    ProposeAuthority {
+        authority: Pubkey,
+    },
+    SetTimelockDelay {
+        delay: u64,
+    },
+    SetApprovers {
+        approvers: [Pubkey; MAX_APPROVERS], // the first approver_count are the new set
+        approver_count: u8,
+        threshold: u8,
+    },
+    AddTreasuryWallet {
+        wallet: Pubkey,
+    },
+    RemoveTreasuryWallet {
+        wallet: Pubkey,
+    },
+    // switches sol purchases to oracle pricing, where tokens are only priced in usd and sol
+    // is converted at the feed price. the default pubkey goes back to the fixed sol prices.
+    SetPriceFeed {
+        price_feed: Pubkey,
+        max_price_age: u64,
+        max_confidence_bps: u16,
+    },
+    UpdatePaymentMint {
+        mint: Pubkey,
+        usd_pegged: bool,
+        price_feed: Pubkey,
+        min_investment: u64,
+        max_investment: u64,
+        is_active: bool,
+    },
+}
+
+impl AdminAction {
+    // role needed to queue, execute or cancel the action
+    pub fn role(&self) -> Role {
+        match self {
+            AdminAction::ChangePrice { .. } | AdminAction::SetPriceFeed { .. } => {
+                Role::PriceManager
+            }
+            AdminAction::WithdrawStakingTokens { .. } => Role::Treasurer,
+            AdminAction::ProposeAuthority { .. }
+            | AdminAction::SetTimelockDelay { .. }
+            | AdminAction::SetApprovers { .. }
+            | AdminAction::AddTreasuryWallet { .. }
+            | AdminAction::RemoveTreasuryWallet { .. }
+            | AdminAction::UpdatePaymentMint { .. } => Role::SuperAdmin,
+        }
+    }
+}
+
+// M-of-N approvers of treasury withdrawals
+#[account]
+#[derive(Default)]
+pub struct Multisig {
+    pub approvers: [Pubkey; MAX_APPROVERS],
+    pub approver_count: u8,
+    pub threshold: u8, // approvals needed to execute a withdrawal
+    pub version: u64,  // bumped on every change of the approvers, stale proposals can't execute
+    pub proposal_count: u64,
+}
+
+impl Multisig {
+    pub fn approver_index(&self, key: &Pubkey) -> Option<usize> {
+        self.approvers[..self.approver_count as usize]
+            .iter()
+            .position(|approver| approver == key)
+    }
+}
+
+// wallet allowed to receive treasury withdrawals, seeded by its key
+#[account]
+#[derive(Default)]
+pub struct TreasuryWallet {
+    pub wallet: Pubkey,
+}
+
+// withdrawal waiting for approvals, seeded by its id
+#[account]
+#[derive(Default)]
+pub struct WithdrawalProposal {
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub payment_token_mint: Pubkey, // default pubkey withdraws sol
+    pub destination: Pubkey,        // treasury wallet receiving the sol or owning the token account
+    pub amount: u64,
+    pub approvals: [bool; MAX_APPROVERS], // by index in the approver set
+    pub approval_count: u8,
+    pub approvers_version: u64,
+    pub executed: bool,
+}
+
+#[account]
+#[derive(Default)]
+pub struct BonusConfig {
+    pub tier_count: u8,
+    pub tiers: [BonusTier; MAX_BONUS_TIERS], // sorted by min_tokens
+    pub early_bonus_bps: u16,                // bonus for purchases made early in the presale
+    pub early_bonus_duration: u64,           // seconds after start_time the early bonus lasts
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct BonusTier {
+    pub min_tokens: u64, // purchases of at least this many tokens get the tier bonus
+    pub bonus_bps: u16,
+}
+
+impl BonusConfig {
+    // bonus for a purchase of `tokens`: the highest volume tier it reaches plus the early bonus
+    pub fn bonus_tokens(&self, presale: &PresaleInfo, tokens: u64, cur_timestamp: u64) -> u64 {
+        (tokens as u128 * self.bonus_bps(presale, tokens, cur_timestamp) as u128
+            / BPS_DENOMINATOR as u128) as u64
+    }
+
+    // bonus rate of a purchase of `tokens`, it never drops as `tokens` grows
+    pub fn bonus_bps(&self, presale: &PresaleInfo, tokens: u64, cur_timestamp: u64) -> u64 {
+        let volume_bps = self.tiers[..self.tier_count as usize]
+            .iter()
+            .rev()
+            .find(|tier| tokens >= tier.min_tokens)
+            .map_or(0, |tier| tier.bonus_bps);
+        let early_bps =
+            if cur_timestamp < presale.start_time.saturating_add(self.early_bonus_duration) {
+                self.early_bonus_bps
+            } else {
+                0
+            };
+        u64::from(volume_bps) + u64::from(early_bps)
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct PaymentMint {
+    pub payment_token_mint: Pubkey,
+    pub decimals: u8,
+    pub usd_pegged: bool, // priced at the usd price of the presale or the active round
+    pub price_feed: Pubkey, // pyth usd price of the mint, used when not usd pegged
+    pub min_investment: u64, // min per purchase in mint units
+    pub max_investment: u64, // max per wallet in mint units, 0 means no limit
+    pub amount_raised: u64, // total raised in mint units
+    pub is_active: bool,
+}
+
+impl PaymentMint {
+    pub fn is_wrapped_sol(&self) -> bool {
+        self.payment_token_mint == native_mint::ID
+    }
+
+    // usd price of a mint that isn't usd pegged, which needs its feed passed as `price_feed`
+    pub fn load_feed(
+        &self,
+        presale: &PresaleInfo,
+        price_feed: &Option<UncheckedAccount>,
+        cur_timestamp: u64,
+    ) -> Result<Option<PriceFeed>> {
+        if self.usd_pegged {
+            return Ok(None);
+        }
+        let price_feed = price_feed.as_ref().ok_or(CustomError::InvalidPriceFeed)?;
+        let feed = PriceFeed::load(presale, &self.price_feed, price_feed, cur_timestamp)?;
+        Ok(Some(feed))
+    }
+
+    // price per token in mint units for a token priced at `price_per_token_in_usdc`
+    pub fn price(&self, feed: &Option<PriceFeed>, price_per_token_in_usdc: u64) -> Result<u64> {
+        match feed {
+            Some(feed) => feed.price_per_token_in(self.decimals, price_per_token_in_usdc),
+            None => convert_decimals(price_per_token_in_usdc, USDC_DECIMALS, self.decimals, true),
+        }
+    }
+
+    // usd value of `amount` mint units with 6 decimals, rounded down
+    pub fn usd_value(&self, feed: &Option<PriceFeed>, amount: u64) -> Result<u64> {
+        match feed {
+            Some(feed) => feed.usd_value(self.decimals, amount),
+            None => convert_decimals(amount, self.decimals, USDC_DECIMALS, false),
+        }
+    }
+
+    pub fn record_payment(
+        &mut self,
+        payment_data: &mut PaymentInvestmentData,
+        value: u64,
+    ) -> Result<()> {
+        payment_data.investment_amount = payment_data
+            .investment_amount
+            .checked_add(value)
+            .ok_or(CustomError::Overflow)?;
+        require!(
+            self.max_investment == 0 || payment_data.investment_amount <= self.max_investment,
+            CustomError::InvestmentLimitExceeded
+        );
+        self.amount_raised = self
+            .amount_raised
+            .checked_add(value)
+            .ok_or(CustomError::Overflow)?;
+        Ok(())
+    }
+}
+
+// what a wallet paid with one payment mint, needed to refund that mint
+#[account]
+#[derive(Default)]
+pub struct PaymentInvestmentData {
+    pub investment_amount: u64,
+}
+
+////////////////////////////////////////////////////////////
+//                        Helpers
+////////////////////////////////////////////////////////////
+
+// usd price of sol or a payment mint read from a pyth v2 price account
+pub struct PriceFeed {
+    pub price: u64,
+    pub conf: u64,
+    pub expo: i32,
+    pub timestamp: i64,
+}
+
+impl PriceFeed {
+    // reads the aggregate price of the `feed` account and rejects feeds that aren't trading,
+    // are stale or whose confidence interval is too wide
+    pub fn load(
+        presale: &PresaleInfo,
+        feed: &Pubkey,
+        price_feed: &AccountInfo,
+        cur_timestamp: u64,
+    ) -> Result<Self> {
+        require!(
+            price_feed.key() == *feed && PYTH_ORACLE_PROGRAMS.contains(price_feed.owner),
+            CustomError::InvalidPriceFeed
+        );
+        let data = price_feed.try_borrow_data()?;
+        require!(
+            data.len() >= PYTH_ACCOUNT_LEN
+                && read_u32(&data, 0) == PYTH_MAGIC
+                && read_u32(&data, 8) == PYTH_PRICE_ACCOUNT_TYPE
+                && read_u32(&data, 224) == PYTH_STATUS_TRADING,
+            CustomError::InvalidPriceFeed
+        );
+
+        let expo = read_u32(&data, 20) as i32;
+        let timestamp = read_u64(&data, 96) as i64;
+        let price = read_u64(&data, 208) as i64;
+        let conf = read_u64(&data, 216);
+        require!(price > 0, CustomError::InvalidPriceFeed);
+        let price = price as u64;
+
+        let age = cur_timestamp.saturating_sub(u64::try_from(timestamp).unwrap_or(0));
+        require!(age <= presale.max_price_age, CustomError::StalePrice);
+        require!(
+            conf as u128 * BPS_DENOMINATOR as u128
+                <= price as u128 * presale.max_confidence_bps as u128,
+            CustomError::PriceConfidenceTooWide
+        );
+
+        Ok(PriceFeed {
+            price,
+            conf,
+            expo,
+            timestamp,
+        })
+    }
+
+    // units of an asset with `decimals` per token for a token priced at `price_per_token_in_usdc`.
+    // units = usdc units * 10^(decimals - 6) / (price * 10^expo), rounded up
+    pub fn price_per_token_in(&self, decimals: u8, price_per_token_in_usdc: u64) -> Result<u64> {
+        let scale = i32::from(decimals) - i32::from(USDC_DECIMALS) - self.expo;
+        let (numerator, denominator) = if scale >= 0 {
+            (
+                (price_per_token_in_usdc as u128)
+                    .checked_mul(
+                        10u128
+                            .checked_pow(scale as u32)
+                            .ok_or(CustomError::Overflow)?,
+                    )
+                    .ok_or(CustomError::Overflow)?,
+                self.price as u128,
+            )
+        } else {
+            (
+                price_per_token_in_usdc as u128,
+                (self.price as u128)
+                    .checked_mul(
+                        10u128
+                            .checked_pow((-scale) as u32)
+                            .ok_or(CustomError::Overflow)?,
+                    )
+                    .ok_or(CustomError::Overflow)?,
+            )
+        };
+        u64::try_from(numerator.div_ceil(denominator)).map_err(|_| error!(CustomError::Overflow))
+    }
+
+    // usdc units worth `amount` units of an asset with `decimals`.
+    // usdc units = amount * price * 10^(expo + 6 - decimals), rounded down
+    pub fn usd_value(&self, decimals: u8, amount: u64) -> Result<u64> {
+        let value = (amount as u128)
+            .checked_mul(self.price as u128)
+            .ok_or(CustomError::Overflow)?;
+        let scale = self.expo + i32::from(USDC_DECIMALS) - i32::from(decimals);
+        let factor = 10u128
+            .checked_pow(scale.unsigned_abs())
+            .ok_or(CustomError::Overflow)?;
+        let value = if scale >= 0 {
+            value.checked_mul(factor).ok_or(CustomError::Overflow)?
+        } else {
+            value / factor
+        };
+        u64::try_from(value).map_err(|_| error!(CustomError::Overflow))
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+// converts a payment amount into presale tokens at `price` per token
+pub fn calculate_tokens(value: u64, price: u64) -> Result<u64> {
+    require!(price > 0, CustomError::InvalidPrice);
+
+    let number_of_tokens = value.checked_mul(PRECISION).ok_or(CustomError::Overflow)? / price;
+    Ok(number_of_tokens)
+}
+
+// returns the round a purchase is priced against. once the current round has sold out
+// or expired the presale moves on to `next_round`, which the buyer has to pass in.
+// it can be any later round: rounds don't overlap, so every round between the current
+// one and a round that has started is over, and several expired rounds are skipped at once.
+pub fn select_round<'a, 'info>(
+    presale: &mut PresaleInfo,
+    round: &'a mut Option<Box<Account<'info, PresaleRound>>>,
+    next_round: &'a mut Option<Box<Account<'info, PresaleRound>>>,
+    cur_timestamp: u64,
+) -> Result<&'a mut Account<'info, PresaleRound>> {
+    let round = round.as_deref_mut().ok_or(CustomError::RoundRequired)?;
+    let round = if round.is_over(cur_timestamp) {
+        let next_round = next_round.as_deref_mut().ok_or(CustomError::RoundEnded)?;
+        require!(next_round.index > round.index, CustomError::RoundEnded);
+        presale.current_round = next_round.index;
+        next_round
+    } else {
+        round
+    };
+
+    require!(
+        cur_timestamp >= round.start_time,
+        CustomError::RoundNotStarted
+    );
+    require!(!round.is_over(cur_timestamp), CustomError::RoundEnded);
+    Ok(round)
+}
+
+// what a purchase is charged and how it counts towards the sol and usd caps and limits
+pub struct Purchase {
+    pub value: u64,            // amount charged in the payment currency
+    pub sol_value: u64,        // lamports, when paid in sol or wrapped sol
+    pub usd_value: u64,        // usd with 6 decimals, when paid with another payment mint
+    pub number_of_tokens: u64, // tokens bought
+    pub bonus_tokens: u64,     // extra tokens from the bonus tiers
+    pub referral_tokens: u64,  // reward credited to the referrer of the buyer
+}
+
+// scales `amount` between decimal places, rounding down unless `round_up` is set
+pub fn convert_decimals(
+    amount: u64,
+    from_decimals: u8,
+    to_decimals: u8,
+    round_up: bool,
+) -> Result<u64> {
+    let amount = amount as u128;
+    let converted = if to_decimals >= from_decimals {
+        let factor = 10u128
+            .checked_pow(u32::from(to_decimals - from_decimals))
+            .ok_or(CustomError::Overflow)?;
+        amount.checked_mul(factor).ok_or(CustomError::Overflow)?
+    } else {
+        let factor = 10u128
+            .checked_pow(u32::from(from_decimals - to_decimals))
+            .ok_or(CustomError::Overflow)?;
+        if round_up {
+            amount.div_ceil(factor)
+        } else {
+            amount / factor
+        }
+    };
+    u64::try_from(converted).map_err(|_| error!(CustomError::Overflow))
+}
+
+// the authority holds the super admin, price manager and pauser roles. the treasurer and
+// the staking manager are only the keys granted those roles in `roles`, the authority has
+// to grant them to itself to act as either.
+pub fn has_role(
+    roles: Option<&Account<Roles>>,
+    authority: &Pubkey,
+    signer: &Pubkey,
+    role: Role,
+) -> bool {
+    if signer == authority && !matches!(role, Role::Treasurer | Role::StakingManager) {
+        return true;
+    }
+    role != Role::SuperAdmin && roles.is_some_and(|roles| roles.holder(role) == *signer)
+}
+
+// mints that aren't usd pegged need a feed. wrapped sol is priced like sol, so it can't
+// be usd pegged or have a feed of its own.
+pub fn validate_payment_pricing(
+    mint: &Pubkey,
+    usd_pegged: bool,
+    price_feed: &Pubkey,
+) -> Result<()> {
+    if *mint == native_mint::ID {
+        require!(
+            !usd_pegged && *price_feed == Pubkey::default(),
+            CustomError::WrappedSolPayment
+        );
+    } else {
+        require!(
+            usd_pegged || *price_feed != Pubkey::default(),
+            CustomError::InvalidPriceFeed
+        );
+    }
+    Ok(())
+}
+
+// approver sets need 1 to MAX_APPROVERS distinct keys and a threshold they can reach
+pub fn validate_approvers(approvers: &[Pubkey], threshold: u8) -> Result<()> {
+    require!(
+        !approvers.is_empty()
+            && approvers.len() <= MAX_APPROVERS
+            && threshold > 0
+            && threshold as usize <= approvers.len(),
+        CustomError::InvalidApprovers
+    );
+    for (i, approver) in approvers.iter().enumerate() {
+        require!(
+            *approver != Pubkey::default() && !approvers[..i].contains(approver),
+            CustomError::InvalidApprovers
+        );
+    }
+    Ok(())
+}
+
+// grows an account of an older layout to `new_len` bytes, the new bytes are zeroed.
+// `payer` pays the extra rent.
+pub fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    new_len: usize,
+) -> Result<()> {
+    let old_len = account.data_len();
+    require!(old_len < new_len, CustomError::AlreadyMigrated);
+
+    let rent = Rent::get()?;
+    let extra_rent = rent.minimum_balance(new_len) - rent.minimum_balance(old_len);
+    let transfer_instruction =
+        solana_program::system_instruction::transfer(payer.key, account.key, extra_rent);
+    anchor_lang::solana_program::program::invoke(
+        &transfer_instruction,
+        &[
+            payer.to_account_info(),
+            account.clone(),
+            system_program.to_account_info(),
+        ],
+    )?;
+    account.realloc(new_len, true)?;
+    Ok(())
+}
+
+// rewards the older version owes a position of `balance` staked since `stake_date`, counted
+// until `end_time`. it paid each position its share of the month's daily reward per day staked.
+pub fn legacy_rewards(
+    balance: u64,
+    stake_date: u64,
+    staking_start_date: u64,
+    total_staked: u64,
+    end_time: u64,
+) -> Result<u64> {
+    if balance == 0 || total_staked == 0 || end_time <= stake_date {
+        return Ok(0);
+    }
+    let user_share = (balance as u128 * PRECISION as u128 / total_staked as u128) as u64;
+    let stake_duration_days =
+        ((end_time - stake_date) * PRECISION + (DAY_DURATION / 2)) / DAY_DURATION;
+
+    let mut reward_accumulated: u64 = 0;
+    let mut remaining_days = stake_duration_days;
+    let mut current_month = ((stake_date.saturating_sub(staking_start_date) * PRECISION
+        + (MONTH_DURATION / 2))
+        / MONTH_DURATION) as usize;
+
+    while remaining_days > 0 && current_month < LEGACY_DAILY_REWARDS.len() {
+        let days_in_month = std::cmp::min(remaining_days, 30);
+        let scaled_reward = (user_share as u128
+            * LEGACY_DAILY_REWARDS[current_month] as u128
+            * days_in_month as u128
+            / PRECISION as u128) as u64;
+        reward_accumulated = reward_accumulated
+            .checked_add(scaled_reward)
+            .ok_or(CustomError::Overflow)?;
+        remaining_days -= days_in_month;
+        current_month += 1;
+    }
+
+    // the older version paid at least one day once a day was staked
+    if stake_duration_days >= 1 && reward_accumulated == 0 {
+        reward_accumulated = user_share * LEGACY_DAILY_REWARDS[0] / PRECISION;
+    }
+    Ok(reward_accumulated)
+}
+
+// upper bound of what legacy_rewards owes all positions together: their shares add up to at
+// most one, and none counts more days than passed since the staking start, or 12 months
+pub fn legacy_rewards_bound(staking_start_date: u64, end_time: u64) -> Result<u64> {
+    let days = (end_time.saturating_sub(staking_start_date) * PRECISION + (DAY_DURATION / 2))
+        / DAY_DURATION;
+    let days = days.min(LEGACY_DAILY_REWARDS.len() as u64 * 30);
+    let max_daily_reward = LEGACY_DAILY_REWARDS
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or_default();
+    max_daily_reward
+        .checked_mul(days)
+        .ok_or(CustomError::Overflow.into())
+}
+
+// key of an optional account, the default pubkey when it isn't passed
+pub fn optional_key<T: Key>(account: Option<&T>) -> Pubkey {
+    account.map(|account| account.key()).unwrap_or_default()
+}
+
+// tokens that can be bought out of `available` when `extra_bps` of them are reserved on top
+pub fn tokens_before_reserve(available: u64, extra_bps: u64) -> u64 {
+    if available == u64::MAX {
+        return u64::MAX;
+    }
+    (available as u128 * BPS_DENOMINATOR as u128 / (BPS_DENOMINATOR + extra_bps) as u128) as u64
+}
+
+// tokens that `hard_cap - amount_raised` still buys at `price`, a cap of 0 means no limit
+pub fn tokens_until_cap(hard_cap: u64, amount_raised: u64, price: u64) -> u64 {
+    if hard_cap == 0 || price == 0 {
+        return u64::MAX;
+    }
+    let remaining = hard_cap.saturating_sub(amount_raised) as u128;
+    let tokens = remaining * PRECISION as u128 / price as u128;
+    u64::try_from(tokens).unwrap_or(u64::MAX)
+}
+
+// prices a purchase paid in sol, or with `payment_mint` when one is passed, against the
+// active round or the flat presale prices when no rounds have been created. purchases
+// that would go over a hard cap or the round allocation are partially filled, so the
+// charged value can be lower than the requested one. the bonus of the purchase, and its
+// referral reward when `referred` is set, count against the token cap and the round allocation.
+#[allow(clippy::too_many_arguments)]
+pub fn price_purchase<'info>(
+    presale: &mut PresaleInfo,
+    round: &mut Option<Box<Account<'info, PresaleRound>>>,
+    next_round: &mut Option<Box<Account<'info, PresaleRound>>>,
+    price_feed: &Option<UncheckedAccount<'info>>,
+    payment_mint: &Option<Box<Account<'info, PaymentMint>>>,
+    bonus_config: &Option<Box<Account<'info, BonusConfig>>>,
+    referred: bool,
+    value: u64,
+    cur_timestamp: u64,
+) -> Result<Purchase> {
+    let mut round = if presale.total_rounds > 0 {
+        Some(select_round(presale, round, next_round, cur_timestamp)?)
+    } else {
+        None
+    };
+    let (price_per_token_in_sol, price_per_token_in_usdc, round_remaining) = match &round {
+        Some(round) => (
+            round.price_per_token_in_sol,
+            round.price_per_token_in_usdc,
+            round.token_allocation.saturating_sub(round.tokens_sold),
+        ),
+        None => (
+            presale.price_per_token_in_sol,
+            presale.price_per_token_in_usdc,
+            u64::MAX,
+        ),
+    };
+
+    if let Some(payment_mint) = payment_mint.as_deref() {
+        require!(payment_mint.is_active, CustomError::PaymentMintDisabled);
+        require!(
+            value >= payment_mint.min_investment,
+            CustomError::WrongAmount
+        );
+    }
+
+    // tokens the sol and usd caps still allow, the token cap is applied below
+    let mut available = u64::MAX;
+    // usd price of a payment mint that isn't usd pegged
+    let mut mint_feed = None;
+    let price = match payment_mint.as_deref() {
+        Some(payment_mint) if !payment_mint.is_wrapped_sol() => {
+            mint_feed = payment_mint.load_feed(presale, price_feed, cur_timestamp)?;
+            require!(
+                payment_mint.usd_value(&mint_feed, value)? >= presale.min_usdc_investment,
+                CustomError::WrongAmount
+            );
+            available = available.min(tokens_until_cap(
+                presale.hard_cap_usdc,
+                presale.usdc_amount_raised,
+                price_per_token_in_usdc,
+            ));
+            payment_mint.price(&mint_feed, price_per_token_in_usdc)?
+        }
+        // wrapped sol is priced and capped like sol
+        _ => {
+            let price_per_token_in_sol = if presale.oracle_enabled() {
+                // sol is converted at the oracle price, the token is only priced in usd
+                let price_feed = price_feed.as_ref().ok_or(CustomError::InvalidPriceFeed)?;
+                let feed =
+                    PriceFeed::load(presale, &presale.price_feed, price_feed, cur_timestamp)?;
+                feed.price_per_token_in(SOL_DECIMALS, price_per_token_in_usdc)?
+            } else {
+                price_per_token_in_sol
+            };
+            require!(
+                value >= presale.min_sol_investment,
+                CustomError::WrongAmount
+            );
+            available = available.min(tokens_until_cap(
+                presale.hard_cap_sol,
+                presale.sol_amount_raised,
+                price_per_token_in_sol,
+            ));
+            price_per_token_in_sol
+        }
+    };
+
+    let mut number_of_tokens = calculate_tokens(value, price)?;
+    // bonus and referral tokens come out of the token cap and the round allocation too.
+    // the bonus rate of the full purchase is reserved, a partial fill can't reach a higher one.
+    let bonus_bps = bonus_config.as_ref().map_or(0, |bonus_config| {
+        bonus_config.bonus_bps(presale, number_of_tokens, cur_timestamp)
+    });
+    let referral_bps = if referred {
+        u64::from(presale.referral_bonus_bps)
+    } else {
+        0
+    };
+    let available = available.min(tokens_before_reserve(
+        presale.available_tokens().min(round_remaining),
+        bonus_bps + referral_bps,
+    ));
+    let mut value = value;
+    if number_of_tokens > available {
+        require!(available > 0, CustomError::HardCapReached);
+        // only charge for the tokens that are left, rounding the payment up
+        number_of_tokens = available;
+        value =
+            u64::try_from((number_of_tokens as u128 * price as u128).div_ceil(PRECISION as u128))
+                .map_err(|_| CustomError::Overflow)?;
+    }
+
+    let bonus_tokens = bonus_config.as_ref().map_or(0, |bonus_config| {
+        bonus_config.bonus_tokens(presale, number_of_tokens, cur_timestamp)
+    });
+    let referral_tokens =
+        (number_of_tokens as u128 * referral_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+    let purchase = match payment_mint.as_deref() {
+        Some(payment_mint) if !payment_mint.is_wrapped_sol() => Purchase {
+            value,
+            sol_value: 0,
+            usd_value: payment_mint.usd_value(&mint_feed, value)?,
+            number_of_tokens,
+            bonus_tokens,
+            referral_tokens,
+        },
+        _ => Purchase {
+            value,
+            sol_value: value,
+            usd_value: 0,
+            number_of_tokens,
+            bonus_tokens,
+            referral_tokens,
+        },
+    };
+    if let Some(round) = round.as_mut() {
+        round.record_sale(&purchase)?;
+    }
+    Ok(purchase)
+}
+
+// whitelist leaves are keccak256(buyer || allocation as little endian u64).
+// nodes are hashed in sorted order, so proofs don't need to carry left/right flags.
+pub fn verify_merkle_proof(proof: &[[u8; 32]], root: [u8; 32], leaf: [u8; 32]) -> bool {
+    let mut computed = leaf;
+    for node in proof {
+        computed = if computed <= *node {
+            keccak::hashv(&[&computed, node]).0
+        } else {
+            keccak::hashv(&[node, &computed]).0
+        };
+    }
+    computed == root
+}
+
+// the max limits apply to everything a wallet has invested. sol and usd are normalized
+// as fractions of their own limit, so e.g. half the sol limit leaves half the usd limit.
+// other payment mints count at their usd value, wrapped sol counts as sol.
+pub fn check_investment_limits(
+    presale: &PresaleInfo,
+    user_data: &InvestmentData,
+    purchase: &Purchase,
+) -> Result<()> {
+    let max_sol = presale.max_sol_investment as u128;
+    let max_usdc = presale.max_usdc_investment as u128;
+
+    let sol_invested = user_data.sol_investment_amount as u128 + purchase.sol_value as u128;
+    let usdc_invested = user_data.usdc_investment_amount as u128 + purchase.usd_value as u128;
+
+    let within_limit = match (max_sol, max_usdc) {
+        (0, 0) => true,
+        (_, 0) => sol_invested <= max_sol,
+        (0, _) => usdc_invested <= max_usdc,
+        _ => sol_invested * max_usdc + usdc_invested * max_sol <= max_sol * max_usdc,
+    };
+    require!(within_limit, CustomError::InvestmentLimitExceeded);
+    Ok(())
+}
+
+// during the whitelist phase the buyer must prove their leaf, and an allocation above 0
+// caps the tokens they can buy across invest and buy_and_stake, bonus tokens included
+pub fn check_whitelist(
+    presale: &PresaleInfo,
+    user_data: &InvestmentData,
+    buyer: &Pubkey,
+    proof: &[[u8; 32]],
+    allocation: u64,
+    number_of_tokens: u64,
+    cur_timestamp: u64,
+) -> Result<()> {
+    if !presale.whitelist_active(cur_timestamp) {
+        return Ok(());
+    }
+
+    let leaf = keccak::hashv(&[buyer.as_ref(), &allocation.to_le_bytes()]).0;
+    require!(
+        verify_merkle_proof(proof, presale.merkle_root, leaf),
+        CustomError::NotWhitelisted
+    );
+
+    if allocation > 0 {
+        let purchased = user_data
+            .number_of_tokens
+            .checked_add(user_data.staked_tokens)
+            .and_then(|tokens| tokens.checked_add(number_of_tokens))
+            .ok_or(CustomError::Overflow)?;
+        require!(
+            purchased <= allocation,
+            CustomError::WhitelistAllocationExceeded
+        );
+    }
+    Ok(())
+}
+
+// credits the referrer of a purchase with the referred volume and bonus tokens.
+// a wallet is bound to the referrer of its first purchase and referrers must have bought
+// before the wallets they refer, so self referrals and referral loops are impossible.
+// a bound wallet has to pass its referrer with every later purchase too.
+pub fn record_referral(
+    presale: &mut PresaleInfo,
+    user_data: &mut InvestmentData,
+    buyer: &Pubkey,
+    referrer: Option<&UncheckedAccount>,
+    referrer_data: Option<&Account<InvestmentData>>,
+    referral_data: Option<&mut Account<ReferralData>>,
+    purchase: &Purchase,
+) -> Result<()> {
+    let Some(referrer) = referrer else {
+        require!(
+            user_data.referrer == Pubkey::default(),
+            CustomError::ReferrerRequired
+        );
+        return Ok(());
+    };
+    let referral_data = referral_data.ok_or(CustomError::InvalidReferrer)?;
+    require!(referrer.key() != *buyer, CustomError::InvalidReferrer);
+
+    if user_data.referrer == Pubkey::default() {
+        let referrer_data = referrer_data.ok_or(CustomError::InvalidReferrer)?;
+        require!(
+            !user_data.has_purchased() && referrer_data.has_purchased(),
+            CustomError::InvalidReferrer
+        );
+        user_data.referrer = referrer.key();
+        referral_data.referred_wallets += 1;
+    }
+    require!(
+        user_data.referrer == referrer.key(),
+        CustomError::InvalidReferrer
+    );
+
+    // the reward was reserved against the caps when the purchase was priced
+    let reward = purchase.referral_tokens;
+    referral_data.referred_sol_amount += purchase.sol_value;
+    referral_data.referred_usdc_amount += purchase.usd_value;
+    referral_data.referred_tokens += purchase.number_of_tokens;
+    referral_data.reward_tokens += reward;
+    presale.referral_rewards += reward;
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////
+//                        Contexts
+////////////////////////////////////////////////////////////
+
+#[derive(Accounts)]
+pub struct Initializer<'info> {
+    #[account(
+        init_if_needed,
+        payer = signer,
+          /*
+        Discriminator: 8 bytes
+        PresaleInfo : size of PresaleInfo
+         */
+        space=8 + std::mem::size_of::<PresaleInfo>(),
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+          /*
+        Discriminator: 8 bytes
+        StakingInfo : size of StakingInfo
+         */
+        space=8 + std::mem::size_of::<StakingInfo>(),
+        seeds = [STAKING_SEED],
+        bump
+    )]
+    pub staking: Box<Account<'info, StakingInfo>>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = presale
+    )]
+    pub presale_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = staking
+    )]
+    pub staking_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        constraint = token_mint.is_initialized,
+    )]
+    pub token_mint: Box<Account<'info, Mint>>, // Token mint account
+    // Presale's USDC Token Account
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = presale
+    )]
+    pub presale_usdc_account: Box<Account<'info, TokenAccount>>,
+    // usdc mint of the cluster, a local stand-in on localnet
+    #[account(
+        constraint = usdc_mint.is_initialized && usdc_mint.decimals == USDC_DECIMALS @ CustomError::InvalidUSDC,
+    )]
+    pub usdc_mint: Box<Account<'info, Mint>>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct MigratePresale<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    /// CHECK: may still have the old PresaleInfo layout, checked in migrate_presale
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED],
+        bump,
+        owner = crate::ID
+    )]
+    pub presale: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateStaking<'info> {
+    #[account(
+        mut,
+        constraint = signer.key() == presale.authority @ CustomError::Unauthorized,
+    )]
+    pub signer: Signer<'info>,
+    #[account(
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+    /// CHECK: may still have the old StakingInfo layout, checked in migrate_staking
+    #[account(
+        mut,
+        seeds = [STAKING_SEED],
+        bump,
+        owner = crate::ID
+    )]
+    pub staking: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateStakingData<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    /// CHECK: may still have the old StakingData layout, checked in migrate_staking_data
+    #[account(
+        mut,
+        seeds = [STAKING_DATA_SEED, signer.key().as_ref()],
+        bump,
+        owner = crate::ID
+    )]
+    pub staking_data: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [STAKING_SEED],
+        bump
+    )]
+    pub staking: Box<Account<'info, StakingInfo>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateInvestmentData<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    /// CHECK: may still have the old InvestmentData layout, checked in migrate_investment_data
+    #[account(
+        mut,
+        seeds = [DATA_SEED, signer.key().as_ref()],
+        bump,
+        owner = crate::ID
+    )]
+    pub data: UncheckedAccount<'info>,
+    #[account(
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+    // needed when the signer paid with usdc
+    #[account(
+        init_if_needed,
+        /*
+        Discriminator: 8 bytes
+        PaymentInvestmentData : size of PaymentInvestmentData
+         */
+        space = 8 + std::mem::size_of::<PaymentInvestmentData>(),
+        payer = signer,
+        seeds = [PAYMENT_DATA_SEED, presale.usdc_mint.as_ref(), signer.key().as_ref()],
+        bump
+    )]
+    pub payment_data: Option<Box<Account<'info, PaymentInvestmentData>>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Invest<'info> {
+    #[account(
+        init_if_needed,
+        /*
+        Discriminator: 8 bytes
+        InvestmentData : size of InvestmentData
+         */
+        space=8 + std::mem::size_of::<InvestmentData>(),
+        payer=from,
+        seeds=[DATA_SEED,from.key().as_ref()],
+        bump
+
+    )]
+    pub data: Box<Account<'info, InvestmentData>>,
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+
+    #[account(
+        mut,
+        seeds = [ROUND_SEED, &[presale.current_round]],
+        bump
+    )]
+    pub round: Option<Box<Account<'info, PresaleRound>>>,
+
+    #[account(
+        mut,
+        seeds = [ROUND_SEED, &[next_round.index]],
+        bump
+    )]
+    pub next_round: Option<Box<Account<'info, PresaleRound>>>,
+
+    /// CHECK: must match presale.price_feed, the data is validated when the price is read
+    pub price_feed: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        seeds = [BONUS_SEED],
+        bump
+    )]
+    pub bonus_config: Option<Box<Account<'info, BonusConfig>>>,
+
+    #[account(mut)]
+    pub from: Signer<'info>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PAYMENT_MINT_SEED, payment_mint.payment_token_mint.as_ref()],
+        bump,
+        has_one = payment_token_mint
+    )]
+    pub payment_mint: Option<Box<Account<'info, PaymentMint>>>,
+
+    #[account(
+        init_if_needed,
+        /*
+        Discriminator: 8 bytes
+        PaymentInvestmentData : size of PaymentInvestmentData
+         */
+        space = 8 + std::mem::size_of::<PaymentInvestmentData>(),
+        payer = from,
+        seeds = [PAYMENT_DATA_SEED, optional_key(payment_token_mint.as_deref()).as_ref(), from.key().as_ref()],
+        bump
+    )]
+    pub payment_data: Option<Box<Account<'info, PaymentInvestmentData>>>,
+
+    pub payment_token_mint: Option<Box<Account<'info, Mint>>>,
+
+    // Presale's payment mint Token Account
+    #[account(
+        mut,
+        associated_token::mint = payment_token_mint,
+        associated_token::authority = presale
+    )]
+    pub presale_payment_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    // Investor's payment mint Token Account
+    #[account(
+        mut,
+        associated_token::mint = payment_token_mint,
+        associated_token::authority = signer
+    )]
+    pub signer_payment_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: wallet that referred the buyer, only its key is used
+    pub referrer: Option<UncheckedAccount<'info>>,
+
+    // the referrer's own investment, it must have bought before referring
+    #[account(
+        seeds = [DATA_SEED, optional_key(referrer.as_ref()).as_ref()],
+        bump
+    )]
+    pub referrer_data: Option<Box<Account<'info, InvestmentData>>>,
+
+    #[account(
+        init_if_needed,
+        /*
+        Discriminator: 8 bytes
+        ReferralData : size of ReferralData
+         */
+        space = 8 + std::mem::size_of::<ReferralData>(),
+        payer = from,
+        seeds = [REFERRAL_SEED, optional_key(referrer.as_ref()).as_ref()],
+        bump
+    )]
+    pub referral_data: Option<Box<Account<'info, ReferralData>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = presale
+    )]
+    pub presale_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = token_mint,
+        associated_token::authority = signer,
+    )]
+    pub signer_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub token_mint: Box<Account<'info, Mint>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct BuyAndStake<'info> {
+    #[account(
+        init_if_needed,
+        /*
+        Discriminator: 8 bytes
+        InvestmentData : size of InvestmentData
+         */
+        space=8 + std::mem::size_of::<InvestmentData>(),
+        payer=signer,
+        seeds=[DATA_SEED,signer.key().as_ref()],
+        bump
+
+    )]
+    pub investment_data: Box<Account<'info, InvestmentData>>,
+
+    #[account(
+        init_if_needed,
+        /*
+        Discriminator: 8 bytes
+        InvestmentData : size of InvestmentData
+         */
+        space = 8 +  std::mem::size_of::<StakingData>(),  
+        payer=signer,
+        seeds=[STAKING_DATA_SEED,signer.key().as_ref()],
+        bump
+
+    )]
+    pub staking_data: Box<Account<'info, StakingData>>,
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+
+    #[account(
+        mut,
+        seeds = [ROUND_SEED, &[presale.current_round]],
+        bump
+    )]
+    pub round: Option<Box<Account<'info, PresaleRound>>>,
+
+    #[account(
+        mut,
+        seeds = [ROUND_SEED, &[next_round.index]],
+        bump
+    )]
+    pub next_round: Option<Box<Account<'info, PresaleRound>>>,
+
+    /// CHECK: must match presale.price_feed, the data is validated when the price is read
+    pub price_feed: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        seeds = [BONUS_SEED],
+        bump
+    )]
+    pub bonus_config: Option<Box<Account<'info, BonusConfig>>>,
+
+    #[account(
+        mut,
+        seeds = [STAKING_SEED],
+        bump
+    )]
+    pub staking: Box<Account<'info, StakingInfo>>,
+    #[account(
+        seeds = [REWARD_SCHEDULE_SEED],
+        bump
+    )]
+    pub reward_schedule: Box<Account<'info, RewardSchedule>>,
+
+    #[account(mut)]
+    pub from: Signer<'info>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = presale
+    )]
+    pub presale_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = staking
+    )]
+    pub staking_token_account: Box<Account<'info, TokenAccount>>,
+
+
+
+    #[account(
+        mut,
+        seeds = [PAYMENT_MINT_SEED, payment_mint.payment_token_mint.as_ref()],
+        bump,
+        has_one = payment_token_mint
+    )]
+    pub payment_mint: Option<Box<Account<'info, PaymentMint>>>,
+
+    #[account(
+        init_if_needed,
+        /*
+        Discriminator: 8 bytes
+        PaymentInvestmentData : size of PaymentInvestmentData
+         */
+        space = 8 + std::mem::size_of::<PaymentInvestmentData>(),
+        payer = signer,
+        seeds = [PAYMENT_DATA_SEED, optional_key(payment_token_mint.as_deref()).as_ref(), signer.key().as_ref()],
+        bump
+    )]
+    pub payment_data: Option<Box<Account<'info, PaymentInvestmentData>>>,
+
+    pub payment_token_mint: Option<Box<Account<'info, Mint>>>,
+
+    // Presale's payment mint Token Account
+    #[account(
+        mut,
+        associated_token::mint = payment_token_mint,
+        associated_token::authority = presale
+    )]
+    pub presale_payment_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    // Investor's payment mint Token Account
+    #[account(
+        mut,
+        associated_token::mint = payment_token_mint,
+        associated_token::authority = signer
+    )]
+    pub signer_payment_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: wallet that referred the buyer, only its key is used
+    pub referrer: Option<UncheckedAccount<'info>>,
+
+    // the referrer's own investment, it must have bought before referring
+    #[account(
+        seeds = [DATA_SEED, optional_key(referrer.as_ref()).as_ref()],
+        bump
+    )]
+    pub referrer_data: Option<Box<Account<'info, InvestmentData>>>,
+
+    #[account(
+        init_if_needed,
+        /*
+        Discriminator: 8 bytes
+        ReferralData : size of ReferralData
+         */
+        space = 8 + std::mem::size_of::<ReferralData>(),
+        payer = signer,
+        seeds = [REFERRAL_SEED, optional_key(referrer.as_ref()).as_ref()],
+        bump
+    )]
+    pub referral_data: Option<Box<Account<'info, ReferralData>>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct Stake<'info> {
+    #[account(
+        init_if_needed,
+        /*
+        Discriminator: 8 bytes
+        InvestmentData : size of InvestmentData
+         */
+        space = 8 +  std::mem::size_of::<StakingData>(),  
+        payer=from,
+        seeds=[STAKING_DATA_SEED,from.key().as_ref()],
+        bump
+
+    )]
+    pub staking_data: Box<Account<'info, StakingData>>,
+
+    #[account(mut)]
+    pub from: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [STAKING_SEED],
+        bump
+    )]
+    pub staking: Box<Account<'info, StakingInfo>>,
+    #[account(
+        seeds = [REWARD_SCHEDULE_SEED],
+        bump
+    )]
+    pub reward_schedule: Box<Account<'info, RewardSchedule>>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = staking
+    )]
+    pub staking_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = signer,
+    )]
+    pub signer_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub token_mint: Box<Account<'info, Mint>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+
+pub struct Unstake<'info> {
+    #[account(
+        mut,
+        seeds = [STAKING_DATA_SEED, signer.key().as_ref()], 
+        bump
+    )]
+    pub staking_data: Box<Account<'info, StakingData>>,
+
+    #[account(mut)]
+    pub from: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [STAKING_SEED],
+        bump
+    )]
+    pub staking: Box<Account<'info, StakingInfo>>,
+    #[account(
+        seeds = [REWARD_SCHEDULE_SEED],
+        bump
+    )]
+    pub reward_schedule: Box<Account<'info, RewardSchedule>>,
+
+    // the vesting schedule of staked presale tokens
+    #[account(
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = staking.key()
+    )]
+    pub staking_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = signer,
+    )]
+    pub signer_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub token_mint: Box<Account<'info, Mint>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct SetAutoCompound<'info> {
+    #[account(
+        mut,
+        seeds = [STAKING_DATA_SEED, signer.key().as_ref()],
+        bump
+    )]
+    pub staking_data: Box<Account<'info, StakingData>>,
+    pub signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Compound<'info> {
+    /// CHECK: staker whose rewards are compounded, only its key is used
+    pub user: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [STAKING_DATA_SEED, user.key().as_ref()],
+        bump
+    )]
+    pub staking_data: Box<Account<'info, StakingData>>,
+    #[account(
+        mut,
+        seeds = [STAKING_SEED],
+        bump
+    )]
+    pub staking: Box<Account<'info, StakingInfo>>,
+    #[account(
+        seeds = [REWARD_SCHEDULE_SEED],
+        bump
+    )]
+    pub reward_schedule: Box<Account<'info, RewardSchedule>>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimTokens<'info> {
+    #[account(
+        mut,
+        seeds = [DATA_SEED, signer.key().as_ref()],
+        bump
+    )]
+    pub data: Box<Account<'info, InvestmentData>>,
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
 
-////////////////////////////////////////////////////////////
-//                        Account States
-////////////////////////////////////////////////////////////
+    #[account(mut)]
+    pub signer: Signer<'info>,
 
-#[account]
-#[derive(Default)]
-pub struct StakingInfo {
-    pub token_mint: Pubkey,
-    pub authority: Pubkey,
-    pub total_tokens_staked: u64,
-    pub total_tokens_rewarded: u64,
-    pub staking_start_date: u64,
-    pub allow_claiming: bool,
+    #[account(
+        constraint = token_mint.key() == presale.token_mint @ CustomError::InvalidToken
+    )]
+    pub token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = presale
+    )]
+    pub presale_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = token_mint,
+        associated_token::authority = signer,
+    )]
+    pub signer_token_account: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
-#[account]
-#[derive(Default)]
-pub struct StakingData {
-    pub total_staking_balance: u64,
-    pub stake_date: u64,
-    pub is_first_time: bool,
+#[derive(Accounts)]
+pub struct ClaimRefund<'info> {
+    #[account(
+        mut,
+        seeds = [DATA_SEED, signer.key().as_ref()],
+        bump
+    )]
+    pub data: Box<Account<'info, InvestmentData>>,
+
+    #[account(
+        mut,
+        seeds = [STAKING_DATA_SEED, signer.key().as_ref()],
+        bump
+    )]
+    pub staking_data: Option<Box<Account<'info, StakingData>>>,
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+
+    #[account(
+        mut,
+        seeds = [STAKING_SEED],
+        bump
+    )]
+    pub staking: Box<Account<'info, StakingInfo>>,
+    #[account(
+        seeds = [REWARD_SCHEDULE_SEED],
+        bump
+    )]
+    pub reward_schedule: Box<Account<'info, RewardSchedule>>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        constraint = token_mint.key() == presale.token_mint @ CustomError::InvalidToken
+    )]
+    pub token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = presale
+    )]
+    pub presale_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = staking
+    )]
+    pub staking_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = signer,
+    )]
+    pub signer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: payment data of wrapped sol, only read if the signer paid with it
+    #[account(
+        seeds = [PAYMENT_DATA_SEED, native_mint::ID.as_ref(), signer.key().as_ref()],
+        bump
+    )]
+    pub wrapped_sol_payment_data: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
-// Account States
-#[account]
-#[derive(Default)]
-pub struct PresaleInfo {
-    pub token_mint: Pubkey,
-    pub sol_amount_raised: u64,  // total sol raised
-    pub usdc_amount_raised: u64, // total sol raised
-    pub total_tokens_sold: u64,  // total token sold
-    pub start_time: u64,
-    pub price_per_token_in_sol: u64,  // price per token in sol
-    pub price_per_token_in_usdc: u64, // price per token in sol
-    pub is_live: bool,                // is presale is live
-    pub is_initialized: bool,         // is presale is initialized
-    pub authority: Pubkey,
+#[derive(Accounts)]
+pub struct ClaimPaymentRefund<'info> {
+    #[account(
+        seeds = [DATA_SEED, signer.key().as_ref()],
+        bump
+    )]
+    pub data: Box<Account<'info, InvestmentData>>,
+
+    #[account(
+        mut,
+        seeds = [PAYMENT_DATA_SEED, payment_token_mint.key().as_ref(), signer.key().as_ref()],
+        bump
+    )]
+    pub payment_data: Box<Account<'info, PaymentInvestmentData>>,
+
+    #[account(
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub payment_token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_token_mint,
+        associated_token::authority = presale
+    )]
+    pub presale_payment_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_token_mint,
+        associated_token::authority = signer
+    )]
+    pub signer_payment_account: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReferralRewards<'info> {
+    #[account(
+        mut,
+        seeds = [REFERRAL_SEED, signer.key().as_ref()],
+        bump
+    )]
+    pub referral_data: Box<Account<'info, ReferralData>>,
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        constraint = token_mint.key() == presale.token_mint @ CustomError::InvalidToken
+    )]
+    pub token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = presale
+    )]
+    pub presale_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = token_mint,
+        associated_token::authority = signer,
+    )]
+    pub signer_token_account: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct SetUsdcMint<'info> {
+    #[account(
+        mut,
+        constraint = has_role(roles.as_deref(), &presale.authority, &signer.key(), Role::SuperAdmin) @ CustomError::Unauthorized,
+    )]
+    pub signer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+    #[account(
+        constraint = usdc_mint.is_initialized && usdc_mint.decimals == USDC_DECIMALS @ CustomError::InvalidUSDC,
+    )]
+    pub usdc_mint: Box<Account<'info, Mint>>,
+    /// CHECK: payment mint registration of usdc_mint, only read if it exists
+    #[account(
+        seeds = [PAYMENT_MINT_SEED, usdc_mint.key().as_ref()],
+        bump
+    )]
+    pub payment_mint: UncheckedAccount<'info>,
+    // Presale's USDC Token Account
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = presale
+    )]
+    pub presale_usdc_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        seeds = [ROLES_SEED],
+        bump
+    )]
+    pub roles: Option<Box<Account<'info, Roles>>>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct AddPaymentMint<'info> {
+    #[account(
+        mut,
+        constraint = signer.key() == presale.authority.key() @ CustomError::Unauthorized,
+    )]
+    pub signer: Signer<'info>,
+    #[account(
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+    #[account(
+        init,
+        payer = signer,
+          /*
+        Discriminator: 8 bytes
+        PaymentMint : size of PaymentMint
+         */
+        space = 8 + std::mem::size_of::<PaymentMint>(),
+        seeds = [PAYMENT_MINT_SEED, payment_token_mint.key().as_ref()],
+        bump
+    )]
+    pub payment_mint: Box<Account<'info, PaymentMint>>,
+    #[account(
+        constraint = payment_token_mint.is_initialized,
+    )]
+    pub payment_token_mint: Box<Account<'info, Mint>>,
+    // Presale's Token Account for the payment mint
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = payment_token_mint,
+        associated_token::authority = presale
+    )]
+    pub presale_payment_account: Box<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
-#[account]
-#[derive(Default)]
-pub struct InvestmentData {
-    pub sol_investment_amount: u64,
-    pub usdc_investment_amount: u64,
-    pub number_of_tokens: u64,
+#[derive(Accounts)]
+pub struct UpdatePaymentMint<'info> {
+    #[account(
+        mut,
+        constraint = signer.key() == presale.authority.key() @ CustomError::Unauthorized,
+    )]
+    pub signer: Signer<'info>,
+    #[account(
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+    #[account(
+        mut,
+        seeds = [ACTION_SEED, timelock_action.id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub timelock_action: Box<Account<'info, TimelockAction>>,
+    #[account(
+        mut,
+        seeds = [PAYMENT_MINT_SEED, payment_mint.payment_token_mint.as_ref()],
+        bump
+    )]
+    pub payment_mint: Box<Account<'info, PaymentMint>>,
 }
 
-////////////////////////////////////////////////////////////
-//                        Contexts
-////////////////////////////////////////////////////////////
-
 #[derive(Accounts)]
-pub struct Initializer<'info> {
+pub struct SetApprovers<'info> {
+    #[account(
+        mut,
+        constraint = signer.key() == presale.authority.key() @ CustomError::Unauthorized,
+    )]
+    pub signer: Signer<'info>,
+    #[account(
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+    #[account(
+        mut,
+        seeds = [ACTION_SEED, timelock_action.id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub timelock_action: Box<Account<'info, TimelockAction>>,
     #[account(
         init_if_needed,
         payer = signer,
           /*
         Discriminator: 8 bytes
-        PresaleInfo : size of PresaleInfo
+        Multisig : size of Multisig
          */
-        space=8 + std::mem::size_of::<PresaleInfo>(),
+        space = 8 + std::mem::size_of::<Multisig>(),
+        seeds = [MULTISIG_SEED],
+        bump
+    )]
+    pub multisig: Box<Account<'info, Multisig>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct AddTreasuryWallet<'info> {
+    #[account(
+        mut,
+        constraint = signer.key() == presale.authority.key() @ CustomError::Unauthorized,
+    )]
+    pub signer: Signer<'info>,
+    #[account(
         seeds = [PRESALE_SEED],
         bump
     )]
     pub presale: Box<Account<'info, PresaleInfo>>,
-
     #[account(
-        init_if_needed,
+        mut,
+        seeds = [ACTION_SEED, timelock_action.id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub timelock_action: Box<Account<'info, TimelockAction>>,
+    #[account(
+        init,
         payer = signer,
           /*
         Discriminator: 8 bytes
-        StakingInfo : size of StakingInfo
+        TreasuryWallet : size of TreasuryWallet
          */
-        space=8 + std::mem::size_of::<StakingInfo>(),
-        seeds = [STAKING_SEED],
+        space = 8 + std::mem::size_of::<TreasuryWallet>(),
+        seeds = [TREASURY_WALLET_SEED, wallet.as_ref()],
         bump
     )]
-    pub staking: Box<Account<'info, StakingInfo>>,
+    pub treasury_wallet: Box<Account<'info, TreasuryWallet>>,
+    pub system_program: Program<'info, System>,
+}
 
+#[derive(Accounts)]
+pub struct RemoveTreasuryWallet<'info> {
     #[account(
         mut,
-        associated_token::mint = token_mint,
-        associated_token::authority = presale
+        constraint = signer.key() == presale.authority.key() @ CustomError::Unauthorized,
     )]
-    pub presale_token_account: Box<Account<'info, TokenAccount>>,
+    pub signer: Signer<'info>,
     #[account(
-        mut,
-        associated_token::mint = token_mint,
-        associated_token::authority = staking
+        seeds = [PRESALE_SEED],
+        bump
     )]
-    pub staking_token_account: Box<Account<'info, TokenAccount>>,
+    pub presale: Box<Account<'info, PresaleInfo>>,
     #[account(
-        constraint = token_mint.is_initialized == true,
+        mut,
+        seeds = [ACTION_SEED, timelock_action.id.to_le_bytes().as_ref()],
+        bump
     )]
-    pub token_mint: Box<Account<'info, Mint>>, // Token mint account
-    // Presale's USDC Token Account
+    pub timelock_action: Box<Account<'info, TimelockAction>>,
     #[account(
         mut,
-        associated_token::mint = usdc_mint,
-        associated_token::authority = presale
+        close = signer,
+        seeds = [TREASURY_WALLET_SEED, treasury_wallet.wallet.as_ref()],
+        bump
     )]
-    pub presale_usdc_account: Box<Account<'info, TokenAccount>>,
-    #[account(mut)]
-    pub usdc_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
-    pub signer: Signer<'info>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub treasury_wallet: Box<Account<'info, TreasuryWallet>>,
 }
 
 #[derive(Accounts)]
-pub struct Invest<'info> {
+#[instruction(payment_token_mint: Pubkey, destination: Pubkey)]
+pub struct ProposeWithdrawal<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
     #[account(
-        init_if_needed,
-        /*
+        mut,
+        seeds = [MULTISIG_SEED],
+        bump
+    )]
+    pub multisig: Box<Account<'info, Multisig>>,
+    #[account(
+        init,
+        payer = signer,
+          /*
         Discriminator: 8 bytes
-        InvestmentData : size of InvestmentData
+        WithdrawalProposal : size of WithdrawalProposal
          */
-        space=8 + std::mem::size_of::<InvestmentData>(),
-        payer=from,
-        seeds=[DATA_SEED,from.key().as_ref()],
+        space = 8 + std::mem::size_of::<WithdrawalProposal>(),
+        seeds = [WITHDRAWAL_SEED, multisig.proposal_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Box<Account<'info, WithdrawalProposal>>,
+    #[account(
+        seeds = [TREASURY_WALLET_SEED, destination.as_ref()],
+        bump
+    )]
+    pub treasury_wallet: Box<Account<'info, TreasuryWallet>>,
+    #[account(
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+    // registration of payment_token_mint, not needed for sol and the usdc mint
+    #[account(
+        seeds = [PAYMENT_MINT_SEED, payment_token_mint.as_ref()],
         bump
+    )]
+    pub payment_mint: Option<Box<Account<'info, PaymentMint>>>,
+    pub system_program: Program<'info, System>,
+}
 
+#[derive(Accounts)]
+pub struct ApproveWithdrawal<'info> {
+    pub signer: Signer<'info>,
+    #[account(
+        seeds = [MULTISIG_SEED],
+        bump
     )]
-    pub data: Box<Account<'info, InvestmentData>>,
+    pub multisig: Box<Account<'info, Multisig>>,
+    #[account(
+        mut,
+        seeds = [WITHDRAWAL_SEED, proposal.id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Box<Account<'info, WithdrawalProposal>>,
+}
 
+#[derive(Accounts)]
+pub struct ExecuteWithdrawal<'info> {
+    #[account(
+        mut,
+        constraint = multisig.approver_index(&signer.key()).is_some() @ CustomError::NotApprover,
+    )]
+    pub signer: Signer<'info>,
     #[account(
         mut,
         seeds = [PRESALE_SEED],
         bump
     )]
     pub presale: Box<Account<'info, PresaleInfo>>,
+    #[account(
+        seeds = [MULTISIG_SEED],
+        bump
+    )]
+    pub multisig: Box<Account<'info, Multisig>>,
+    #[account(
+        mut,
+        seeds = [WITHDRAWAL_SEED, proposal.id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Box<Account<'info, WithdrawalProposal>>,
 
-    #[account(mut)]
-    pub from: Signer<'info>,
-    #[account(mut)]
-    pub signer: Signer<'info>,
+    /// CHECK: must match the proposal, receives the sol or owns destination_token_account
+    #[account(
+        mut,
+        constraint = destination.key() == proposal.destination @ CustomError::Unauthorized,
+    )]
+    pub destination: UncheckedAccount<'info>,
+    #[account(
+        seeds = [TREASURY_WALLET_SEED, destination.key().as_ref()],
+        bump
+    )]
+    pub treasury_wallet: Box<Account<'info, TreasuryWallet>>,
 
-    // Presale's USDC Token Account
+    // token accounts are only needed to withdraw a payment token
+    #[account(
+        constraint = payment_token_mint.key() == proposal.payment_token_mint @ CustomError::InvalidPaymentToken
+    )]
+    pub payment_token_mint: Option<Box<Account<'info, Mint>>>,
     #[account(
         mut,
-        associated_token::mint = usdc_mint,
+        associated_token::mint = payment_token_mint,
         associated_token::authority = presale
     )]
-    pub presale_usdc_account: Box<Account<'info, TokenAccount>>,
-
-    // Investor's USDC Token Account
+    pub presale_payment_account: Option<Box<Account<'info, TokenAccount>>>,
     #[account(
         mut,
-        associated_token::mint = usdc_mint,
-        associated_token::authority = signer
+        associated_token::mint = payment_token_mint,
+        associated_token::authority = destination
     )]
-    pub signer_usdc_account: Box<Account<'info, TokenAccount>>,
+    pub destination_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Program<'info, Token>,
+}
 
+#[derive(Accounts)]
+pub struct AdminWithdrawTokens<'info> {
     #[account(
         mut,
-        constraint = usdc_mint.key() == Pubkey::from_str(USDC_ADDRESS).map_err(|_| CustomError::InvalidUSDC)? @ CustomError::InvalidUSDC
+        constraint = has_role(roles.as_deref(), &presale.authority, &signer.key(), Role::Treasurer) @ CustomError::Unauthorized,
     )]
-    pub usdc_mint: Box<Account<'info, Mint>>,
-
+    pub signer: Signer<'info>,
     #[account(
         mut,
         associated_token::mint = token_mint,
-        associated_token::authority = presale
+        associated_token::authority = presale.key()
     )]
     pub presale_token_account: Box<Account<'info, TokenAccount>>,
 
     #[account(
-        init_if_needed,
-        payer = signer,
+        mut,
+        seeds = [PRESALE_SEED],
+        bump,
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+
+    /// CHECK: owner of destination_token_account, must be a treasury wallet
+    pub destination: UncheckedAccount<'info>,
+    #[account(
+        mut,
         associated_token::mint = token_mint,
-        associated_token::authority = signer,
+        associated_token::authority = destination,
     )]
-    pub signer_token_account: Box<Account<'info, TokenAccount>>,
+    pub destination_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        seeds = [TREASURY_WALLET_SEED, destination.key().as_ref()],
+        bump
+    )]
+    pub treasury_wallet: Box<Account<'info, TreasuryWallet>>,
 
     #[account(mut)]
     pub token_mint: Box<Account<'info, Mint>>,
@@ -682,329 +4462,400 @@ pub struct Invest<'info> {
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub associated_token_program: Program<'info, AssociatedToken>,
+    #[account(
+        seeds = [ROLES_SEED],
+        bump
+    )]
+    pub roles: Option<Box<Account<'info, Roles>>>,
 }
 
 #[derive(Accounts)]
-pub struct BuyAndStake<'info> {
+pub struct UnlockStaking<'info> {
     #[account(
-        init_if_needed,
-        /*
-        Discriminator: 8 bytes
-        InvestmentData : size of InvestmentData
-         */
-        space=8 + std::mem::size_of::<InvestmentData>(),
-        payer=signer,
-        seeds=[DATA_SEED,signer.key().as_ref()],
+        mut,
+        constraint = has_role(roles.as_deref(), &presale.authority, &signer.key(), Role::StakingManager) @ CustomError::Unauthorized,
+    )]
+    pub signer: Signer<'info>,
+    #[account(
+        seeds = [PRESALE_SEED],
         bump
-
     )]
-    pub investment_data: Box<Account<'info, InvestmentData>>,
-
+    pub presale: Box<Account<'info, PresaleInfo>>,
     #[account(
-        init_if_needed,
-        /*
-        Discriminator: 8 bytes
-        InvestmentData : size of InvestmentData
-         */
-        space = 8 +  std::mem::size_of::<StakingData>(),  
-        payer=signer,
-        seeds=[STAKING_DATA_SEED,signer.key().as_ref()],
+        mut,
+        seeds = [STAKING_SEED],
+        bump
+    )]
+    pub staking: Box<Account<'info, StakingInfo>>,
+    #[account(
+        seeds = [ROLES_SEED],
         bump
+    )]
+    pub roles: Option<Box<Account<'info, Roles>>>,
+}
 
+#[derive(Accounts)]
+#[instruction(period_duration: u64, emissions: Vec<u64>)]
+pub struct CreateRewardSchedule<'info> {
+    #[account(
+        mut,
+        constraint = has_role(roles.as_deref(), &presale.authority, &signer.key(), Role::StakingManager) @ CustomError::Unauthorized,
     )]
-    pub staking_data: Box<Account<'info, StakingData>>,
+    pub signer: Signer<'info>,
+    #[account(
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
+    #[account(
+        seeds = [STAKING_SEED],
+        bump
+    )]
+    pub staking: Box<Account<'info, StakingInfo>>,
+    #[account(
+        init,
+        payer = signer,
+        space = RewardSchedule::space(emissions.len()),
+        seeds = [REWARD_SCHEDULE_SEED],
+        bump
+    )]
+    pub reward_schedule: Box<Account<'info, RewardSchedule>>,
+    #[account(
+        seeds = [ROLES_SEED],
+        bump
+    )]
+    pub roles: Option<Box<Account<'info, Roles>>>,
+    pub system_program: Program<'info, System>,
+}
 
+#[derive(Accounts)]
+#[instruction(emissions: Vec<u64>)]
+pub struct AppendRewardPeriods<'info> {
     #[account(
         mut,
+        constraint = has_role(roles.as_deref(), &presale.authority, &signer.key(), Role::StakingManager) @ CustomError::Unauthorized,
+    )]
+    pub signer: Signer<'info>,
+    #[account(
         seeds = [PRESALE_SEED],
         bump
     )]
     pub presale: Box<Account<'info, PresaleInfo>>,
-
     #[account(
         mut,
         seeds = [STAKING_SEED],
         bump
     )]
     pub staking: Box<Account<'info, StakingInfo>>,
-
-    #[account(mut)]
-    pub from: Signer<'info>,
-    #[account(mut)]
-    pub signer: Signer<'info>,
-
-    #[account(mut)]
-    pub token_mint: Box<Account<'info, Mint>>,
-
     #[account(
         mut,
-        associated_token::mint = token_mint,
-        associated_token::authority = presale
+        seeds = [REWARD_SCHEDULE_SEED],
+        bump,
+        realloc = RewardSchedule::space(reward_schedule.emissions.len() + emissions.len()),
+        realloc::payer = signer,
+        realloc::zero = false,
     )]
-    pub presale_token_account: Box<Account<'info, TokenAccount>>,
+    pub reward_schedule: Box<Account<'info, RewardSchedule>>,
+    #[account(
+        seeds = [ROLES_SEED],
+        bump
+    )]
+    pub roles: Option<Box<Account<'info, Roles>>>,
+    pub system_program: Program<'info, System>,
+}
 
+#[derive(Accounts)]
+pub struct StopPresale<'info> {
+    // the role needed depends on the instruction, see require_role
+    #[account(mut)]
+    pub signer: Signer<'info>,
     #[account(
         mut,
-        associated_token::mint = token_mint,
-        associated_token::authority = staking
+        seeds = [PRESALE_SEED],
+        bump
     )]
-    pub staking_token_account: Box<Account<'info, TokenAccount>>,
-
-
-
+    pub presale: Box<Account<'info, PresaleInfo>>,
     #[account(
-        constraint = usdc_mint.key() == Pubkey::from_str(USDC_ADDRESS).map_err(|_| CustomError::InvalidUSDC)? @ CustomError::InvalidUSDC
+        seeds = [ROLES_SEED],
+        bump
     )]
-    pub usdc_mint: Box<Account<'info, Mint>>,
+    pub roles: Option<Box<Account<'info, Roles>>>,
+}
 
+impl StopPresale<'_> {
+    pub fn require_role(&self, role: Role) -> Result<()> {
+        require!(
+            has_role(
+                self.roles.as_deref(),
+                &self.presale.authority,
+                &self.signer.key(),
+                role
+            ),
+            CustomError::Unauthorized
+        );
+        Ok(())
+    }
+}
+#[derive(Accounts)]
+pub struct SetBonusTiers<'info> {
     #[account(
         mut,
-        associated_token::mint = usdc_mint,
-        associated_token::authority = presale
+        constraint = signer.key() == presale.authority.key() @ CustomError::Unauthorized,
     )]
-    pub presale_usdc_account: Box<Account<'info, TokenAccount>>,
-
+    pub signer: Signer<'info>,
     #[account(
-        mut,
-        associated_token::mint = usdc_mint,
-        associated_token::authority = signer
+        seeds = [PRESALE_SEED],
+        bump
     )]
-    pub signer_usdc_account: Box<Account<'info, TokenAccount>>,
-
-    pub token_program: Program<'info, Token>,
+    pub presale: Box<Account<'info, PresaleInfo>>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+          /*
+        Discriminator: 8 bytes
+        BonusConfig : size of BonusConfig
+         */
+        space = 8 + std::mem::size_of::<BonusConfig>(),
+        seeds = [BONUS_SEED],
+        bump
+    )]
+    pub bonus_config: Box<Account<'info, BonusConfig>>,
     pub system_program: Program<'info, System>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
 }
-
 #[derive(Accounts)]
-pub struct Stake<'info> {
+pub struct ManageRoles<'info> {
+    #[account(
+        mut,
+        constraint = signer.key() == presale.authority.key() @ CustomError::Unauthorized,
+    )]
+    pub signer: Signer<'info>,
+    #[account(
+        seeds = [PRESALE_SEED],
+        bump
+    )]
+    pub presale: Box<Account<'info, PresaleInfo>>,
     #[account(
         init_if_needed,
-        /*
+        payer = signer,
+          /*
         Discriminator: 8 bytes
-        InvestmentData : size of InvestmentData
+        Roles : size of Roles
          */
-        space = 8 +  std::mem::size_of::<StakingData>(),  
-        payer=from,
-        seeds=[STAKING_DATA_SEED,from.key().as_ref()],
+        space = 8 + std::mem::size_of::<Roles>(),
+        seeds = [ROLES_SEED],
         bump
-
     )]
-    pub staking_data: Box<Account<'info, StakingData>>,
+    pub roles: Box<Account<'info, Roles>>,
+    pub system_program: Program<'info, System>,
+}
 
+#[derive(Accounts)]
+pub struct QueueAction<'info> {
+    // the role needed depends on the action
     #[account(mut)]
-    pub from: Signer<'info>,
+    pub signer: Signer<'info>,
     #[account(
         mut,
-        seeds = [STAKING_SEED],
+        seeds = [PRESALE_SEED],
         bump
     )]
-    pub staking: Box<Account<'info, StakingInfo>>,
-
+    pub presale: Box<Account<'info, PresaleInfo>>,
     #[account(
-        mut,
-        associated_token::mint = token_mint,
-        associated_token::authority = staking
+        init,
+        payer = signer,
+          /*
+        Discriminator: 8 bytes
+        TimelockAction : size of TimelockAction
+         */
+        space = 8 + std::mem::size_of::<TimelockAction>(),
+        seeds = [ACTION_SEED, presale.action_count.to_le_bytes().as_ref()],
+        bump
     )]
-    pub staking_token_account: Box<Account<'info, TokenAccount>>,
-
+    pub timelock_action: Box<Account<'info, TimelockAction>>,
     #[account(
-        mut,
-        associated_token::mint = token_mint,
-        associated_token::authority = signer,
+        seeds = [ROLES_SEED],
+        bump
     )]
-    pub signer_token_account: Box<Account<'info, TokenAccount>>,
-
-    #[account(mut)]
-    pub signer: Signer<'info>,
-
-    #[account(mut)]
-    pub token_mint: Box<Account<'info, Mint>>,
-
-    pub token_program: Program<'info, Token>,
+    pub roles: Option<Box<Account<'info, Roles>>>,
     pub system_program: Program<'info, System>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
 #[derive(Accounts)]
-
-pub struct Unstake<'info> {
+pub struct ExecuteAction<'info> {
+    // the role needed depends on the action
+    #[account(mut)]
+    pub signer: Signer<'info>,
     #[account(
         mut,
-        seeds = [STAKING_DATA_SEED, signer.key().as_ref()], 
+        seeds = [PRESALE_SEED],
         bump
     )]
-    pub staking_data: Box<Account<'info, StakingData>>,
-
-    #[account(mut)]
-    pub from: Signer<'info>,
-
+    pub presale: Box<Account<'info, PresaleInfo>>,
     #[account(
         mut,
         seeds = [STAKING_SEED],
         bump
     )]
     pub staking: Box<Account<'info, StakingInfo>>,
+    #[account(
+        mut,
+        seeds = [ACTION_SEED, timelock_action.id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub timelock_action: Box<Account<'info, TimelockAction>>,
+    #[account(
+        seeds = [ROLES_SEED],
+        bump
+    )]
+    pub roles: Option<Box<Account<'info, Roles>>>,
 
+    // the accounts below are only needed to withdraw staking tokens
+    #[account(
+        constraint = token_mint.key() == presale.token_mint @ CustomError::InvalidToken
+    )]
+    pub token_mint: Option<Box<Account<'info, Mint>>>,
     #[account(
         mut,
         associated_token::mint = token_mint,
-        associated_token::authority = staking.key()
+        associated_token::authority = staking
     )]
-    pub staking_token_account: Box<Account<'info, TokenAccount>>,
-
+    pub staking_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        seeds = [REWARD_SCHEDULE_SEED],
+        bump
+    )]
+    pub reward_schedule: Option<Box<Account<'info, RewardSchedule>>>,
+    /// CHECK: owner of destination_token_account, must be the treasury wallet of the action
+    pub destination: Option<UncheckedAccount<'info>>,
     #[account(
         mut,
         associated_token::mint = token_mint,
-        associated_token::authority = signer,
+        associated_token::authority = destination
     )]
-    pub signer_token_account: Box<Account<'info, TokenAccount>>,
-
-    #[account(mut)]
-    pub signer: Signer<'info>,
-
-    #[account(mut)]
-    pub token_mint: Box<Account<'info, Mint>>,
-
+    pub destination_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        seeds = [TREASURY_WALLET_SEED, optional_key(destination.as_ref()).as_ref()],
+        bump
+    )]
+    pub treasury_wallet: Option<Box<Account<'info, TreasuryWallet>>>,
     pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
 #[derive(Accounts)]
-pub struct AdminWithdrawUsdcSol<'info> {
-    #[account(
-        mut,
-        constraint = signer.key() == presale.authority.key() @ CustomError::Unauthorized,
-    )]
+pub struct CancelAction<'info> {
+    // the role needed depends on the action
     pub signer: Signer<'info>,
-
     #[account(
-        mut,
         seeds = [PRESALE_SEED],
         bump
     )]
     pub presale: Box<Account<'info, PresaleInfo>>,
-
-    // Presale's USDC Token Account (from where USDC is withdrawn)
     #[account(
         mut,
-        associated_token::mint = usdc_mint,
-        associated_token::authority = presale
+        seeds = [ACTION_SEED, timelock_action.id.to_le_bytes().as_ref()],
+        bump
     )]
-    pub presale_usdc_account: Box<Account<'info, TokenAccount>>,
-
-    // Admin's USDC Token Account (where USDC is sent)
+    pub timelock_action: Box<Account<'info, TimelockAction>>,
     #[account(
-        mut,
-        associated_token::mint = usdc_mint,
-        associated_token::authority = signer
+        seeds = [ROLES_SEED],
+        bump
     )]
-    pub signer_usdc_account: Box<Account<'info, TokenAccount>>,
-
-    #[account(mut)]
-    pub usdc_mint: Box<Account<'info, Mint>>,
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub roles: Option<Box<Account<'info, Roles>>>,
 }
 
 #[derive(Accounts)]
-pub struct AdminWithdrawTokens<'info> {
+pub struct AcceptAuthority<'info> {
     #[account(
         mut,
-        constraint = signer.key() == staking.authority.key() @ CustomError::Unauthorized,
+        constraint = presale.pending_authority != Pubkey::default() @ CustomError::NoPendingAuthority,
+        constraint = signer.key() == presale.pending_authority @ CustomError::Unauthorized,
     )]
     pub signer: Signer<'info>,
-    #[account(
-        mut,
-        associated_token::mint = token_mint,
-        associated_token::authority = staking.key()
-    )]
-    pub staking_token_account: Box<Account<'info, TokenAccount>>,
-    #[account(
-        mut,
-        associated_token::mint = token_mint,
-        associated_token::authority = presale.key()
-    )]
-    pub presale_token_account: Box<Account<'info, TokenAccount>>,
-
     #[account(
         mut,
         seeds = [PRESALE_SEED],
-        bump,
+        bump
     )]
     pub presale: Box<Account<'info, PresaleInfo>>,
-    #[account(
-        mut,
-        associated_token::mint = token_mint,
-        associated_token::authority = signer,
-    )]
-    pub signer_token_account: Box<Account<'info, TokenAccount>>,
-
     #[account(
         mut,
         seeds = [STAKING_SEED],
         bump
     )]
     pub staking: Box<Account<'info, StakingInfo>>,
-
-    #[account(mut)]
-    pub token_mint: Box<Account<'info, Mint>>,
-
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
 #[derive(Accounts)]
-pub struct UnlockStaking<'info> {
+pub struct CreateRound<'info> {
     #[account(
         mut,
-        constraint = signer.key() == staking.authority.key() @ CustomError::Unauthorized,
+        constraint = has_role(roles.as_deref(), &presale.authority, &signer.key(), Role::PriceManager) @ CustomError::Unauthorized,
     )]
     pub signer: Signer<'info>,
     #[account(
         mut,
-        seeds = [STAKING_SEED],
+        seeds = [PRESALE_SEED],
         bump
     )]
-    pub staking: Box<Account<'info, StakingInfo>>,
+    pub presale: Box<Account<'info, PresaleInfo>>,
+    #[account(
+        init,
+        payer = signer,
+          /*
+        Discriminator: 8 bytes
+        PresaleRound : size of PresaleRound
+         */
+        space = 8 + std::mem::size_of::<PresaleRound>(),
+        seeds = [ROUND_SEED, &[presale.total_rounds]],
+        bump
+    )]
+    pub round: Box<Account<'info, PresaleRound>>,
+    #[account(
+        seeds = [ROUND_SEED, &[presale.total_rounds.wrapping_sub(1)]],
+        bump
+    )]
+    pub previous_round: Option<Box<Account<'info, PresaleRound>>>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        seeds = [ROLES_SEED],
+        bump
+    )]
+    pub roles: Option<Box<Account<'info, Roles>>>,
 }
 
 #[derive(Accounts)]
-pub struct StopPresale<'info> {
+pub struct UpdateRound<'info> {
     #[account(
         mut,
-        constraint = signer.key() == presale.authority.key() @ CustomError::Unauthorized,
+        constraint = has_role(roles.as_deref(), &presale.authority, &signer.key(), Role::PriceManager) @ CustomError::Unauthorized,
     )]
     pub signer: Signer<'info>,
     #[account(
-        mut,
         seeds = [PRESALE_SEED],
         bump
     )]
     pub presale: Box<Account<'info, PresaleInfo>>,
-}
-#[derive(Accounts)]
-pub struct TransferOwnership<'info> {
     #[account(
         mut,
-        constraint = signer.key() == presale.authority.key() @ CustomError::Unauthorized,
+        seeds = [ROUND_SEED, &[round.index]],
+        bump
     )]
-    pub signer: Signer<'info>,
+    pub round: Box<Account<'info, PresaleRound>>,
     #[account(
-        mut,
-        seeds = [PRESALE_SEED],
+        seeds = [ROUND_SEED, &[round.index.wrapping_sub(1)]],
         bump
     )]
-    pub presale: Box<Account<'info, PresaleInfo>>,
+    pub previous_round: Option<Box<Account<'info, PresaleRound>>>,
     #[account(
-        mut,
-        seeds = [STAKING_SEED],
+        seeds = [ROUND_SEED, &[round.index.wrapping_add(1)]],
         bump
     )]
-    pub staking: Box<Account<'info, StakingInfo>>,
+    pub next_round: Option<Box<Account<'info, PresaleRound>>>,
+    #[account(
+        seeds = [ROLES_SEED],
+        bump
+    )]
+    pub roles: Option<Box<Account<'info, Roles>>>,
 }
 
 ////////////////////////////////////////////////////////////
@@ -1038,4 +4889,110 @@ pub enum CustomError {
     NoRewards,
     #[msg("InvalidPaymentToken")]
     InvalidPaymentToken,
+    #[msg("InvalidPrice")]
+    InvalidPrice,
+    #[msg("Presale round account required")]
+    RoundRequired,
+    #[msg("Presale round not started")]
+    RoundNotStarted,
+    #[msg("Presale round ended")]
+    RoundEnded,
+    #[msg("Presale round already started")]
+    RoundAlreadyStarted,
+    #[msg("Purchase exceeds the round allocation")]
+    RoundAllocationExceeded,
+    #[msg("Too many presale rounds")]
+    TooManyRounds,
+    #[msg("Hard cap reached")]
+    HardCapReached,
+    #[msg("Soft cap must not exceed the hard cap")]
+    InvalidCaps,
+    #[msg("Presale already finalized")]
+    PresaleFinalized,
+    #[msg("Presale failed, funds are reserved for refunds")]
+    PresaleFailed,
+    #[msg("Refunds are only available for a failed presale")]
+    RefundNotAvailable,
+    #[msg("Nothing to refund")]
+    NothingToRefund,
+    #[msg("Invalid Token")]
+    InvalidToken,
+    #[msg("Tokens vest, use claim_vested")]
+    VestingEnabled,
+    #[msg("Invalid vesting schedule")]
+    InvalidVesting,
+    #[msg("No vested tokens to claim")]
+    NothingVested,
+    #[msg("Not whitelisted")]
+    NotWhitelisted,
+    #[msg("Purchase exceeds the whitelist allocation")]
+    WhitelistAllocationExceeded,
+    #[msg("Purchase exceeds the wallet investment limit")]
+    InvestmentLimitExceeded,
+    #[msg("Invalid price feed")]
+    InvalidPriceFeed,
+    #[msg("Price feed is stale")]
+    StalePrice,
+    #[msg("Price confidence interval too wide")]
+    PriceConfidenceTooWide,
+    #[msg("Payment mint disabled")]
+    PaymentMintDisabled,
+    #[msg("Return the purchased tokens with claim_refund first")]
+    TokensNotReturned,
+    #[msg("Presale ended")]
+    PresaleEnded,
+    #[msg("Purchase yields fewer tokens than expected")]
+    SlippageExceeded,
+    #[msg("Invalid referrer")]
+    InvalidReferrer,
+    #[msg("Invalid bonus tiers")]
+    InvalidBonusTiers,
+    #[msg("No pending authority transfer")]
+    NoPendingAuthority,
+    #[msg("The super admin role can only be transferred")]
+    InvalidRole,
+    #[msg("Action was already executed or cancelled")]
+    ActionNotPending,
+    #[msg("Timelock delay has not passed")]
+    TimelockNotExpired,
+    #[msg("Invalid approver set")]
+    InvalidApprovers,
+    #[msg("Signer is not an approver")]
+    NotApprover,
+    #[msg("Already approved")]
+    AlreadyApproved,
+    #[msg("Not enough approvals")]
+    NotEnoughApprovals,
+    #[msg("Proposal was already executed or the approvers changed")]
+    ProposalNotPending,
+    #[msg("Account already has the current size")]
+    AlreadyMigrated,
+    #[msg("Invalid reward schedule")]
+    InvalidRewardSchedule,
+    #[msg("Auto compounding is disabled for this staker")]
+    AutoCompoundDisabled,
+    #[msg("Presale rounds must not overlap")]
+    RoundOverlap,
+    #[msg("Prices are set per round once rounds exist")]
+    RoundPricesActive,
+    #[msg("The soft cap can't change once tokens have been sold")]
+    SoftCapLocked,
+    #[msg("A hard cap can't be below the amount already sold or raised")]
+    CapBelowSold,
+    #[msg("Presale has not ended yet")]
+    PresaleNotEnded,
+    #[msg("Presale not finalized")]
+    PresaleNotFinalized,
+    #[msg("The TGE time can't be changed once set")]
+    TgeLocked,
+    #[msg("Staked presale tokens are still vesting")]
+    TokensLocked,
+    #[msg("Wrapped SOL is priced like SOL and can't be USD pegged or have its own price feed")]
+    WrappedSolPayment,
+    #[msg("Pass the referrer the wallet is bound to")]
+    ReferrerRequired,
+    #[msg("The queued action doesn't match the instruction")]
+    ActionMismatch,
+    #[msg("Round prices have to be set a timelock delay before the round starts")]
+    RoundStartTooSoon,
 }
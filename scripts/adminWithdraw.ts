@@ -85,38 +85,131 @@ async function main() {
           mint: TOKEN_MINT,
           owner: presalePda,
         });
-        const staking_ata = anchor.utils.token.associatedAddress({
-          mint: TOKEN_MINT,
-          owner: stakingPda,
-        });
-    
-        const usdc_presale_ata = anchor.utils.token.associatedAddress({
-          mint: USDC_MINT,
-          owner: presalePda,
-        });
     
-        const usdc_signer_ata = anchor.utils.token.associatedAddress({
-          mint: USDC_MINT,
-          owner: new anchor.web3.PublicKey(
-            "CrepGjpjjaHiXEPhEw2rLywEtjgR9sRvL3LfUrPQq9im"
-          ),
-        });
-    const context = {
-         
-         signer:wallet.publicKey,
-         presale:presalePda,
-         presaleUsdcAccount:usdc_presale_ata,
-         signerUsdAccount:usdc_signer_ata,
-         usdcMint:USDC_MINT,
-         systemProgram: anchor.web3.SystemProgram.programId,
-         tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
-         associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
-       }
-     const configIx = await program.methods
-      .adminWithdrawUsdcAndSol()
-      .accounts(context)
-      .instruction();
-     
+        // usage: adminWithdraw.ts queue-allow <treasury wallet>
+        //        adminWithdraw.ts allow <treasury wallet> <action id>, once the timelock delay passed
+        //        adminWithdraw.ts propose <sol|usdc> <destination> <amount>
+        //        adminWithdraw.ts approve <proposal id>
+        //        adminWithdraw.ts execute <proposal id>
+        //        adminWithdraw.ts withdraw-tokens <treasury wallet> <amount>, needs the treasurer role
+        const [command, ...args] = process.argv.slice(2);
+        const [multisigPda] = anchor.web3.PublicKey.findProgramAddressSync(
+          [Buffer.from("multisig")],
+          PROGRAM_ID
+        );
+        const proposalPda = (id: anchor.BN) =>
+          anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("withdrawal_proposal"), id.toArrayLike(Buffer, "le", 8)],
+            PROGRAM_ID
+          )[0];
+        const treasuryWalletPda = (wallet: anchor.web3.PublicKey) =>
+          anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("treasury_wallet"), wallet.toBuffer()],
+            PROGRAM_ID
+          )[0];
+        const timelockActionPda = (id: anchor.BN) =>
+          anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("timelock_action"), id.toArrayLike(Buffer, "le", 8)],
+            PROGRAM_ID
+          )[0];
+
+        let configIx;
+        if (command === "queue-allow") {
+          const treasuryWallet = new anchor.web3.PublicKey(args[0]);
+          const presale = await program.account.presaleInfo.fetch(presalePda);
+          console.log("action id", presale.actionCount.toString());
+          configIx = await program.methods
+            .queueAction({ addTreasuryWallet: { wallet: treasuryWallet } })
+            .accounts({
+              signer: wallet.publicKey,
+              presale: presalePda,
+              timelockAction: timelockActionPda(presale.actionCount),
+              roles: null,
+            })
+            .instruction();
+        } else if (command === "allow") {
+          const treasuryWallet = new anchor.web3.PublicKey(args[0]);
+          configIx = await program.methods
+            .addTreasuryWallet(treasuryWallet)
+            .accounts({
+              signer: wallet.publicKey,
+              presale: presalePda,
+              timelockAction: timelockActionPda(new BN(args[1])),
+              treasuryWallet: treasuryWalletPda(treasuryWallet),
+            })
+            .instruction();
+        } else if (command === "propose") {
+          const [asset, destination, amount] = args;
+          const multisig = await program.account.multisig.fetch(multisigPda);
+          configIx = await program.methods
+            .proposeWithdrawal(
+              asset === "usdc" ? USDC_MINT : anchor.web3.PublicKey.default,
+              new anchor.web3.PublicKey(destination),
+              new BN(amount)
+            )
+            .accounts({
+              signer: wallet.publicKey,
+              multisig: multisigPda,
+              proposal: proposalPda(multisig.proposalCount),
+              treasuryWallet: treasuryWalletPda(new anchor.web3.PublicKey(destination)),
+              presale: presalePda,
+              // sol and usdc don't need a payment mint registration
+              paymentMint: null,
+            })
+            .instruction();
+        } else if (command === "approve") {
+          configIx = await program.methods
+            .approveWithdrawal()
+            .accounts({
+              signer: wallet.publicKey,
+              multisig: multisigPda,
+              proposal: proposalPda(new BN(args[0])),
+            })
+            .instruction();
+        } else if (command === "withdraw-tokens") {
+          // unsold sale tokens, the ones owed to buyers stay in the presale
+          const destination = new anchor.web3.PublicKey(args[0]);
+          configIx = await program.methods
+            .adminWithdrawTokens(new BN(args[1]))
+            .accounts({
+              signer: wallet.publicKey,
+              presale: presalePda,
+              presaleTokenAccount: presale_ata,
+              destination,
+              destinationTokenAccount: anchor.utils.token.associatedAddress({
+                mint: TOKEN_MINT,
+                owner: destination,
+              }),
+              treasuryWallet: treasuryWalletPda(destination),
+              tokenMint: TOKEN_MINT,
+              roles: anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("roles")], PROGRAM_ID)[0],
+            })
+            .instruction();
+        } else {
+          const proposal = proposalPda(new BN(args[0]));
+          const proposalData = await program.account.withdrawalProposal.fetch(proposal);
+          const isSol = proposalData.paymentTokenMint.equals(anchor.web3.PublicKey.default);
+          configIx = await program.methods
+            .executeWithdrawal()
+            .accounts({
+              signer: wallet.publicKey,
+              presale: presalePda,
+              multisig: multisigPda,
+              proposal,
+              destination: proposalData.destination,
+              treasuryWallet: treasuryWalletPda(proposalData.destination),
+              paymentTokenMint: isSol ? null : proposalData.paymentTokenMint,
+              presalePaymentAccount: isSol ? null : anchor.utils.token.associatedAddress({
+                mint: proposalData.paymentTokenMint,
+                owner: presalePda,
+              }),
+              destinationTokenAccount: isSol ? null : anchor.utils.token.associatedAddress({
+                mint: proposalData.paymentTokenMint,
+                owner: proposalData.destination,
+              }),
+            })
+            .instruction();
+        }
 
             const tx = new Transaction().add(configIx);
 
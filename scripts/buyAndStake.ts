@@ -108,6 +108,16 @@ async function main() {
                 [Buffer.from(DATA_SEED_STAKING),wallet.publicKey.toBuffer()],
                 program.programId
               );
+
+          const [paymentMintPda] = anchor.web3.PublicKey.findProgramAddressSync(
+                [Buffer.from("payment_mint"),USDC_MINT.toBuffer()],
+                program.programId
+              );
+
+          const [paymentDataPda] = anchor.web3.PublicKey.findProgramAddressSync(
+                [Buffer.from("payment_data"),USDC_MINT.toBuffer(),wallet.publicKey.toBuffer()],
+                program.programId
+              );
         console.log("presalePda", presalePda.toString());
         console.log("stakingPda", stakingPda.toString());
         console.log("presale_ata", presale_ata.toString());
@@ -130,9 +140,11 @@ async function main() {
             tokenMint:TOKEN_MINT.toString(),
             presaleTokenAccount:presale_ata.toString(),
             stakingTokenAccount:staking_ata.toString(),
-            usdcMint:USDC_MINT.toString(),
-            presaleUsdcAccount:usdc_presale_ata.toString(),
-            signerUsdAccount:usdc_signer_ata.toString(),
+            paymentMint:paymentMintPda.toString(),
+            paymentData:paymentDataPda.toString(),
+            paymentTokenMint:USDC_MINT.toString(),
+            presalePaymentAccount:usdc_presale_ata.toString(),
+            signerPaymentAccount:usdc_signer_ata.toString(),
             tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID.toString(),
             systemProgram: anchor.web3.SystemProgram.programId.toString(),
             associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID.toString(),
@@ -140,7 +152,7 @@ async function main() {
 
           console.log("context",context)
           // Add your test here.
-          const configIx = await program.methods.buyAndStake(new BN(100e6),1)        
+          const configIx = await program.methods.buyAndStake(new BN(100e6),new BN(0),[],new BN(0))        
           .accounts(context)
           .instruction()
     
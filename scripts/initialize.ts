@@ -122,6 +122,7 @@ const startPresaleContext = {
     // Add your test here.
     const configIx =  await program.methods.initializer(
       new BN(Math.floor((Date.now()/1000))),
+      new BN(Math.floor((Date.now()/1000))+30*24*60*60),
       new BN(431250),
       new BN(70000) 
     )        
@@ -81,17 +81,24 @@ async function main() {
     
 
 
-    // Add your test here.
+    // the new authority still has to sign accept_authority
     const newAuthority = new anchor.web3.PublicKey(
       "HtcmNSmpM6xGWLH7TcUiyjXQcej32qc15wyzawJYKNMn"
     );
+    const presaleData = await program.account.presaleInfo.fetch(presalePda);
+    const [timelockAction] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("timelock_action"),presaleData.actionCount.toArrayLike(Buffer,"le",8)],
+      PROGRAM_ID
+    );
     const context = {
       presale:presalePda,
+      timelockAction:timelockAction,
       signer:wallet.publicKey,
     };
 
+    // queues the proposal, run executeAction once the timelock delay has passed
      const configIx = await program.methods
-      .updateAuthority(newAuthority)
+      .queueAction({proposeAuthority:{authority:newAuthority}})
       .accounts(context)
       .instruction();
      
@@ -0,0 +1,2039 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { BankrunProvider, startAnchor } from "anchor-bankrun";
+import { Clock, ProgramTestContext } from "solana-bankrun";
+import {
+  createAssociatedTokenAccountIdempotentInstruction,
+  createInitializeMint2Instruction,
+  createMintToInstruction,
+  createSyncNativeInstruction,
+  getAssociatedTokenAddressSync,
+  MINT_SIZE,
+  MintLayout,
+  NATIVE_MINT,
+  TOKEN_PROGRAM_ID,
+  unpackAccount,
+} from "@solana/spl-token";
+import { keccak_256 } from "@noble/hashes/sha3";
+import { assert } from "chai";
+import { BN } from "bn.js";
+import { SolanaPresale } from "../target/types/solana_presale";
+
+const IDL = require("../target/idl/solana_presale.json");
+
+// these tests run against a fresh bank each, so a presale can be finalized, refunded or
+// moved forward in time without affecting the others. the clock only moves with warpTo.
+describe("solana presale lifecycle", () => {
+  const DATA_SEED = "my_data";
+  const PRESALE_SEED = "solana_presale";
+  const STAKING_SEED = "solana_staking";
+  const ROUND_SEED = "presale_round";
+  const ACTION_SEED = "timelock_action";
+  const MULTISIG_SEED = "multisig";
+  const WITHDRAWAL_SEED = "withdrawal_proposal";
+  const TREASURY_WALLET_SEED = "treasury_wallet";
+  const REWARD_SCHEDULE_SEED = "reward_schedule";
+  const STAKING_DATA_SEED = "staking_user_data";
+  const PAYMENT_MINT_SEED = "payment_mint";
+  const PAYMENT_DATA_SEED = "payment_data";
+  const REFERRAL_SEED = "referral";
+  const BONUS_SEED = "bonus_config";
+  const ROLES_SEED = "roles";
+
+  const START = 1_900_000_000; // start time of every presale in these tests
+  const END = START + 30 * 24 * 60 * 60;
+  const SOL_PRICE = 368664; // lamports per token
+  const USDC_PRICE = 79067; // usdc units per token
+  const PYTH_ORACLE = new anchor.web3.PublicKey("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH");
+
+  let context: ProgramTestContext;
+  let provider: BankrunProvider;
+  let program: Program<SolanaPresale>;
+  let authority: anchor.web3.Keypair;
+  let token: anchor.web3.PublicKey;
+  let usdc: anchor.web3.PublicKey;
+
+  const [presalePda] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from(PRESALE_SEED)],
+    new anchor.web3.PublicKey(IDL.address)
+  );
+  const [stakingPda] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from(STAKING_SEED)],
+    new anchor.web3.PublicKey(IDL.address)
+  );
+
+  const pda = (...seeds: (Buffer | Uint8Array)[]) =>
+    anchor.web3.PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+  const dataPda = (owner: anchor.web3.PublicKey) => pda(Buffer.from(DATA_SEED), owner.toBuffer());
+  const roundPda = (index: number) => pda(Buffer.from(ROUND_SEED), Buffer.from([index]));
+  const rolesPda = () => pda(Buffer.from(ROLES_SEED));
+  const ata = (mint: anchor.web3.PublicKey, owner: anchor.web3.PublicKey) =>
+    getAssociatedTokenAddressSync(mint, owner, true);
+
+  // moves the bank to `unixTimestamp`. the slot moves too, which gives a fresh blockhash,
+  // so it also has to be called before repeating a transaction that already succeeded.
+  const warpTo = async (unixTimestamp: number) => {
+    const clock = await context.banksClient.getClock();
+    context.warpToSlot(clock.slot + 1n);
+    context.setClock(
+      new Clock(
+        clock.slot + 1n,
+        clock.epochStartTimestamp,
+        clock.epoch,
+        clock.leaderScheduleEpoch,
+        BigInt(unixTimestamp)
+      )
+    );
+  };
+
+  const send = (instructions: anchor.web3.TransactionInstruction[], signers: anchor.web3.Signer[] = []) =>
+    provider.sendAndConfirm(new anchor.web3.Transaction().add(...instructions), signers);
+
+  const createMint = async (decimals: number) => {
+    const mint = anchor.web3.Keypair.generate();
+    const rent = await context.banksClient.getRent();
+    await send(
+      [
+        anchor.web3.SystemProgram.createAccount({
+          fromPubkey: authority.publicKey,
+          newAccountPubkey: mint.publicKey,
+          space: MINT_SIZE,
+          lamports: Number(rent.minimumBalance(BigInt(MINT_SIZE))),
+          programId: TOKEN_PROGRAM_ID,
+        }),
+        createInitializeMint2Instruction(mint.publicKey, decimals, authority.publicKey, null),
+      ],
+      [mint]
+    );
+    return mint.publicKey;
+  };
+
+  // creates the associated token account of `owner` and mints `amount` to it
+  const fundTokens = async (mint: anchor.web3.PublicKey, owner: anchor.web3.PublicKey, amount: number | bigint) => {
+    const account = ata(mint, owner);
+    const instructions = [
+      createAssociatedTokenAccountIdempotentInstruction(authority.publicKey, account, owner, mint),
+    ];
+    if (amount > 0) {
+      instructions.push(createMintToInstruction(mint, account, authority.publicKey, amount));
+    }
+    await send(instructions);
+    return account;
+  };
+
+  const tokenBalance = async (address: anchor.web3.PublicKey) => {
+    const account = await context.banksClient.getAccount(address);
+    if (account === null) {
+      return 0;
+    }
+    return Number(unpackAccount(address, { ...account, data: Buffer.from(account.data) }).amount);
+  };
+
+  const newWallet = (sol: number) => {
+    const wallet = anchor.web3.Keypair.generate();
+    context.setAccount(wallet.publicKey, {
+      lamports: sol * 1e9,
+      data: Buffer.alloc(0),
+      owner: anchor.web3.SystemProgram.programId,
+      executable: false,
+    });
+    return wallet;
+  };
+
+  // rejects unless the transaction fails with the custom error `name`
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    const error = IDL.errors.find((e: { name: string }) => e.name === name);
+    try {
+      await promise;
+    } catch (e) {
+      const message = `${e} ${e.logs ? e.logs.join(" ") : ""}`;
+      assert(
+        message.includes(name) || (error && message.includes(`0x${error.code.toString(16)}`)),
+        `expected ${name}, got ${message}`
+      );
+      return;
+    }
+    assert.fail(`expected ${name}`);
+  };
+
+  // fresh bank with an initialized presale from START to END, holding 1M tokens
+  // for sale and 100k staking reward tokens
+  const setupPresale = async () => {
+    context = await startAnchor("", [], []);
+    provider = new BankrunProvider(context);
+    program = new Program<SolanaPresale>(IDL, provider);
+    authority = context.payer;
+    await warpTo(START);
+
+    usdc = await createMint(6);
+    token = await createMint(5);
+    await fundTokens(token, presalePda, 1_000_000e5);
+    await fundTokens(token, stakingPda, 100_000e5);
+    await fundTokens(usdc, presalePda, 0);
+
+    await program.methods
+      .initializer(new BN(START), new BN(END), new BN(SOL_PRICE), new BN(USDC_PRICE))
+      .accounts({
+        signer: authority.publicKey,
+        presale: presalePda,
+        staking: stakingPda,
+        tokenMint: token,
+        usdcMint: usdc,
+        presaleUsdcAccount: ata(usdc, presalePda),
+        stakingTokenAccount: ata(token, stakingPda),
+        presaleTokenAccount: ata(token, presalePda),
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+      })
+      .rpc();
+
+    // the authority doesn't hold the staking manager role unless it grants it to itself
+    await program.methods
+      .grantRole({ stakingManager: {} }, authority.publicKey)
+      .accounts({ signer: authority.publicKey, presale: presalePda, roles: rolesPda() })
+      .rpc();
+    await program.methods
+      .createRewardSchedule(new BN(24 * 60 * 60), Array(30).fill(new BN(1000e5)))
+      .accounts({
+        signer: authority.publicKey,
+        presale: presalePda,
+        staking: stakingPda,
+        rewardSchedule: pda(Buffer.from(REWARD_SCHEDULE_SEED)),
+        roles: rolesPda(),
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+  };
+
+  // accounts of a sol purchase by `buyer`, extended or overridden by `accounts`
+  const investContext = (buyer: anchor.web3.Keypair, accounts: object = {}) => ({
+    data: dataPda(buyer.publicKey),
+    from: buyer.publicKey,
+    signer: buyer.publicKey,
+    presale: presalePda,
+    presaleTokenAccount: ata(token, presalePda),
+    tokenMint: token,
+    signerTokenAccount: ata(token, buyer.publicKey),
+    systemProgram: anchor.web3.SystemProgram.programId,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+    ...accounts,
+  });
+
+  const investSol = (buyer: anchor.web3.Keypair, lamports: number, accounts: object = {}) =>
+    program.methods
+      .invest(new BN(lamports), new BN(0), [], new BN(0))
+      .accounts(investContext(buyer, accounts))
+      .signers([buyer])
+      .rpc();
+
+  const adminContext = () => ({
+    signer: authority.publicKey,
+    presale: presalePda,
+  });
+
+  const setCaps = (hardCapTokens: number, hardCapSol: number, hardCapUsdc: number, softCapTokens: number) =>
+    program.methods
+      .setCaps(new BN(hardCapTokens), new BN(hardCapSol), new BN(hardCapUsdc), new BN(softCapTokens))
+      .accounts(adminContext())
+      .rpc();
+
+  describe("caps", () => {
+    it("partially fills the purchase that reaches the token cap", async () => {
+      await setupPresale();
+      await setCaps(2000e5, 0, 0, 0);
+      const buyer = newWallet(10);
+
+      // 1 sol buys about 2712 tokens, only 2000 are left
+      await investSol(buyer, 1e9);
+      assert.equal(await tokenBalance(ata(token, buyer.publicKey)), 2000e5);
+      const presaleData = await program.account.presaleInfo.fetch(presalePda);
+      assert.equal(presaleData.totalTokensSold.toNumber(), 2000e5);
+      // only the filled part is charged, rounded up
+      assert.equal(presaleData.solAmountRaised.toNumber(), Math.ceil((2000e5 * SOL_PRICE) / 1e5));
+      assert.isFalse(presaleData.isLive);
+
+      await warpTo(START + 1);
+      await expectError(investSol(buyer, 1e9), "PresaleNotLive");
+    });
+
+    it("partially fills the purchase that reaches the sol cap", async () => {
+      await setupPresale();
+      await setCaps(0, 0.6e9, 0, 0);
+      const buyer = newWallet(10);
+
+      await investSol(buyer, 1e9);
+      const presaleData = await program.account.presaleInfo.fetch(presalePda);
+      const raised = presaleData.solAmountRaised.toNumber();
+      assert.isAtMost(raised, 0.6e9);
+      assert.isAbove(raised, 0.6e9 - SOL_PRICE);
+      assert.equal(
+        (await program.account.investmentData.fetch(dataPda(buyer.publicKey))).solInvestmentAmount.toNumber(),
+        raised
+      );
+      assert.isFalse(presaleData.isLive);
+    });
+
+    it("keeps caps consistent with what has been sold", async () => {
+      await setupPresale();
+      await setCaps(5000e5, 0, 0, 1000e5);
+      const buyer = newWallet(10);
+      await investSol(buyer, 1e9);
+
+      await expectError(setCaps(2000e5, 0, 0, 1000e5), "CapBelowSold");
+      await expectError(setCaps(5000e5, 0.5e9, 0, 1000e5), "CapBelowSold");
+      await expectError(setCaps(5000e5, 0, 0, 500e5), "SoftCapLocked");
+      await setCaps(10000e5, 2e9, 0, 1000e5);
+      await setCaps(0, 0, 0, 1000e5);
+
+      await warpTo(END + 1);
+      await program.methods.finalizePresale().accounts(adminContext()).rpc();
+      await expectError(setCaps(10000e5, 0, 0, 1000e5), "PresaleFinalized");
+    });
+  });
+
+  const multisigPda = () => pda(Buffer.from(MULTISIG_SEED));
+  const treasuryWalletPda = (wallet: anchor.web3.PublicKey) =>
+    pda(Buffer.from(TREASURY_WALLET_SEED), wallet.toBuffer());
+
+  // queues `action` as the authority and returns the timelock action pda
+  const queueAction = async (action: object) => {
+    const presaleData = await program.account.presaleInfo.fetch(presalePda);
+    const timelockAction = pda(Buffer.from(ACTION_SEED), presaleData.actionCount.toArrayLike(Buffer, "le", 8));
+    await program.methods
+      .queueAction(action as any)
+      .accounts({ signer: authority.publicKey, presale: presalePda, timelockAction, roles: rolesPda() })
+      .rpc();
+    return timelockAction;
+  };
+
+  // moves the bank to when the actions queued now can be executed
+  const waitForTimelock = async () => {
+    const clock = await context.banksClient.getClock();
+    const presaleData = await program.account.presaleInfo.fetch(presalePda);
+    await warpTo(Number(clock.unixTimestamp) + presaleData.timelockDelay.toNumber());
+  };
+
+  const executeAction = (timelockAction: anchor.web3.PublicKey) =>
+    program.methods
+      .executeAction()
+      .accounts({ signer: authority.publicKey, presale: presalePda, staking: stakingPda, timelockAction, roles: rolesPda() })
+      .rpc();
+
+  // approver sets are queued padded to MAX_APPROVERS keys
+  const setApproversAction = (approvers: anchor.web3.PublicKey[], threshold: number) => ({
+    setApprovers: {
+      approvers: [...approvers, ...Array(10 - approvers.length).fill(anchor.web3.PublicKey.default)],
+      approverCount: approvers.length,
+      threshold,
+    },
+  });
+
+  const setApprovers = (timelockAction: anchor.web3.PublicKey) =>
+    program.methods
+      .setApprovers()
+      .accounts({ ...adminContext(), timelockAction, multisig: multisigPda() })
+      .rpc();
+
+  const addTreasuryWallet = (timelockAction: anchor.web3.PublicKey, treasury: anchor.web3.PublicKey) =>
+    program.methods
+      .addTreasuryWallet(treasury)
+      .accounts({ ...adminContext(), timelockAction, treasuryWallet: treasuryWalletPda(treasury) })
+      .rpc();
+
+  // makes the authority the only approver and `treasury` an allowlisted treasury wallet.
+  // both go through the timelock, so the bank moves on by its delay.
+  const setupMultisig = async (treasury: anchor.web3.PublicKey) => {
+    const approversAction = await queueAction(setApproversAction([authority.publicKey], 1));
+    const treasuryAction = await queueAction({ addTreasuryWallet: { wallet: treasury } });
+    await waitForTimelock();
+    await setApprovers(approversAction);
+    await addTreasuryWallet(treasuryAction, treasury);
+  };
+
+  describe("treasury configuration", () => {
+    const DAY = 24 * 60 * 60;
+
+    it("keeps the timelock delay between its bounds", async () => {
+      await setupPresale();
+      assert.equal((await program.account.presaleInfo.fetch(presalePda)).timelockDelay.toNumber(), 2 * DAY);
+      await expectError(queueAction({ setTimelockDelay: { delay: new BN(0) } }), "WrongTime");
+      await expectError(queueAction({ setTimelockDelay: { delay: new BN(DAY - 1) } }), "WrongTime");
+      await expectError(queueAction({ setTimelockDelay: { delay: new BN(30 * DAY + 1) } }), "WrongTime");
+
+      // the new delay waits for the current one
+      const timelockAction = await queueAction({ setTimelockDelay: { delay: new BN(3 * DAY) } });
+      await warpTo(START + 2 * DAY - 1);
+      await expectError(executeAction(timelockAction), "TimelockNotExpired");
+      await warpTo(START + 2 * DAY);
+      await executeAction(timelockAction);
+      assert.equal((await program.account.presaleInfo.fetch(presalePda)).timelockDelay.toNumber(), 3 * DAY);
+    });
+
+    it("changes the approvers only after the timelock delay", async () => {
+      await setupPresale();
+      const approver = newWallet(1);
+      await setupMultisig(anchor.web3.Keypair.generate().publicKey);
+
+      await expectError(queueAction(setApproversAction([approver.publicKey], 2)), "InvalidApprovers");
+      const timelockAction = await queueAction(setApproversAction([authority.publicKey, approver.publicKey], 2));
+      await expectError(setApprovers(timelockAction), "TimelockNotExpired");
+      // the action can't be run as a generic one either
+      await waitForTimelock();
+      await expectError(executeAction(timelockAction), "ActionMismatch");
+
+      await setApprovers(timelockAction);
+      const multisig = await program.account.multisig.fetch(multisigPda());
+      assert.equal(multisig.approverCount, 2);
+      assert.equal(multisig.threshold, 2);
+      assert.isTrue(multisig.approvers[1].equals(approver.publicKey));
+      assert.isTrue(multisig.approvers[2].equals(anchor.web3.PublicKey.default));
+
+      await warpTo(START + 4 * DAY + 1);
+      await expectError(setApprovers(timelockAction), "ActionNotPending");
+    });
+
+    it("allowlists and removes treasury wallets only through matching actions", async () => {
+      await setupPresale();
+      const treasury = anchor.web3.Keypair.generate().publicKey;
+      const other = anchor.web3.Keypair.generate().publicKey;
+      await setupMultisig(treasury);
+
+      const addOther = await queueAction({ addTreasuryWallet: { wallet: other } });
+      const approversAction = await queueAction(setApproversAction([authority.publicKey], 1));
+      const removeOther = await queueAction({ removeTreasuryWallet: { wallet: other } });
+      const removeTreasuryAction = await queueAction({ removeTreasuryWallet: { wallet: treasury } });
+      await waitForTimelock();
+
+      // an action for one wallet can't allowlist another
+      await expectError(addTreasuryWallet(addOther, anchor.web3.Keypair.generate().publicKey), "ActionMismatch");
+      await expectError(addTreasuryWallet(approversAction, other), "ActionMismatch");
+      await addTreasuryWallet(addOther, other);
+
+      const removeTreasury = (timelockAction: anchor.web3.PublicKey) =>
+        program.methods
+          .removeTreasuryWallet()
+          .accounts({ ...adminContext(), timelockAction, treasuryWallet: treasuryWalletPda(treasury) })
+          .rpc();
+      await expectError(removeTreasury(removeOther), "ActionMismatch");
+      await removeTreasury(removeTreasuryAction);
+      assert.isNull(await context.banksClient.getAccount(treasuryWalletPda(treasury)));
+      assert.isNotNull(await context.banksClient.getAccount(treasuryWalletPda(other)));
+    });
+  });
+
+  // proposes a withdrawal of `mint` to `destination`, which the single approver can execute
+  // right away. the default pubkey withdraws sol, other mints pass their registration.
+  const proposeWithdrawal = async (
+    mint: anchor.web3.PublicKey,
+    destination: anchor.web3.PublicKey,
+    amount: number,
+    paymentMint: anchor.web3.PublicKey | null = null
+  ) => {
+    const multisig = await program.account.multisig.fetch(multisigPda());
+    const proposal = pda(Buffer.from(WITHDRAWAL_SEED), multisig.proposalCount.toArrayLike(Buffer, "le", 8));
+    await program.methods
+      .proposeWithdrawal(mint, destination, new BN(amount))
+      .accounts({
+        signer: authority.publicKey,
+        multisig: multisigPda(),
+        proposal,
+        treasuryWallet: treasuryWalletPda(destination),
+        presale: presalePda,
+        paymentMint,
+      })
+      .rpc();
+    return proposal;
+  };
+
+  const proposeSolWithdrawal = (destination: anchor.web3.PublicKey, lamports: number) =>
+    proposeWithdrawal(anchor.web3.PublicKey.default, destination, lamports);
+
+  const executeSolWithdrawal = (proposal: anchor.web3.PublicKey, destination: anchor.web3.PublicKey) =>
+    program.methods
+      .executeWithdrawal()
+      .accounts({
+        signer: authority.publicKey,
+        presale: presalePda,
+        multisig: multisigPda(),
+        proposal,
+        destination,
+        treasuryWallet: treasuryWalletPda(destination),
+        paymentTokenMint: null,
+        presalePaymentAccount: null,
+        destinationTokenAccount: null,
+      })
+      .rpc();
+
+  const solBalance = async (address: anchor.web3.PublicKey) =>
+    Number((await context.banksClient.getAccount(address))?.lamports ?? 0);
+
+  // returns the tokens `buyer` bought outside of staking and refunds its sol
+  const claimRefund = (buyer: anchor.web3.Keypair) =>
+    program.methods
+      .claimRefund()
+      .accounts({
+        data: dataPda(buyer.publicKey),
+        stakingData: null,
+        presale: presalePda,
+        staking: stakingPda,
+        rewardSchedule: pda(Buffer.from(REWARD_SCHEDULE_SEED)),
+        signer: buyer.publicKey,
+        tokenMint: token,
+        presaleTokenAccount: ata(token, presalePda),
+        stakingTokenAccount: ata(token, stakingPda),
+        signerTokenAccount: ata(token, buyer.publicKey),
+        wrappedSolPaymentData: pda(Buffer.from(PAYMENT_DATA_SEED), NATIVE_MINT.toBuffer(), buyer.publicKey.toBuffer()),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+      })
+      .signers([buyer])
+      .rpc();
+
+  describe("finalize and refunds", () => {
+    it("finalizes once the presale has ended", async () => {
+      await setupPresale();
+      await setCaps(0, 0, 0, 1000e5);
+      await investSol(newWallet(10), 1e9);
+
+      await expectError(program.methods.finalizePresale().accounts(adminContext()).rpc(), "PresaleNotEnded");
+
+      await warpTo(END);
+      await program.methods.finalizePresale().accounts(adminContext()).rpc();
+      const presaleData = await program.account.presaleInfo.fetch(presalePda);
+      assert.isTrue(presaleData.isFinalized);
+      assert.isTrue(presaleData.isSuccessful);
+      assert.isFalse(presaleData.isLive);
+      await warpTo(END + 1);
+      await expectError(program.methods.finalizePresale().accounts(adminContext()).rpc(), "PresaleFinalized");
+    });
+
+    it("finalizes early once a hard cap is reached", async () => {
+      await setupPresale();
+      await setCaps(2000e5, 0, 0, 0);
+      await investSol(newWallet(10), 1e9);
+
+      await program.methods.finalizePresale().accounts(adminContext()).rpc();
+      assert.isTrue((await program.account.presaleInfo.fetch(presalePda)).isSuccessful);
+    });
+
+    it("refunds buyers of a failed presale", async () => {
+      await setupPresale();
+      await setCaps(0, 0, 0, 100_000e5);
+      const buyer = newWallet(10);
+      const treasury = newWallet(1).publicKey;
+      await setupMultisig(treasury);
+      await investSol(buyer, 1e9);
+      const paid = (await program.account.investmentData.fetch(dataPda(buyer.publicKey))).solInvestmentAmount.toNumber();
+      const bought = await tokenBalance(ata(token, buyer.publicKey));
+      const presaleTokens = await tokenBalance(ata(token, presalePda));
+
+      // no refunds while the presale can still succeed
+      const refund = () => claimRefund(buyer);
+      await expectError(refund(), "RefundNotAvailable");
+
+      await warpTo(END);
+      await program.methods.finalizePresale().accounts(adminContext()).rpc();
+      assert.isFalse((await program.account.presaleInfo.fetch(presalePda)).isSuccessful);
+
+      // the raised sol stays with the buyers
+      const proposal = await proposeSolWithdrawal(treasury, paid);
+      await expectError(executeSolWithdrawal(proposal, treasury), "PresaleFailed");
+
+      const before = await solBalance(buyer.publicKey);
+      await refund();
+      assert.equal(await solBalance(buyer.publicKey), before + paid);
+      assert.equal(await tokenBalance(ata(token, buyer.publicKey)), 0);
+      assert.equal(await tokenBalance(ata(token, presalePda)), presaleTokens + bought);
+      await warpTo(END + 1);
+      await expectError(refund(), "NothingToRefund");
+    });
+
+    it("refunds payment mints once the tokens are returned", async () => {
+      await setupPresale();
+      await setCaps(0, 0, 0, 100_000e5);
+      await addUsdcPaymentMint();
+      const buyer = newWallet(10);
+      await fundTokens(usdc, buyer.publicKey, 1000e6);
+      await investUsdc(buyer, 500e6);
+
+      // a disabled mint takes no new purchases
+      const timelockAction = await queueAction(
+        updatePaymentMintAction(usdc, true, anchor.web3.PublicKey.default, 0, 0, false)
+      );
+      await waitForTimelock();
+      await updatePaymentMint(timelockAction, usdc);
+      await expectError(investUsdc(buyer, 400e6), "PaymentMintDisabled");
+      await expectError(claimPaymentRefund(buyer, usdc), "RefundNotAvailable");
+
+      await warpTo(END);
+      await program.methods.finalizePresale().accounts(adminContext()).rpc();
+      await expectError(claimPaymentRefund(buyer, usdc), "TokensNotReturned");
+
+      // refunds of a disabled mint still work
+      await claimRefund(buyer);
+      await claimPaymentRefund(buyer, usdc);
+      assert.equal(await tokenBalance(ata(usdc, buyer.publicKey)), 1000e6);
+      assert.equal(await tokenBalance(ata(usdc, presalePda)), 0);
+      await warpTo(END + 1);
+      await expectError(claimPaymentRefund(buyer, usdc), "NothingToRefund");
+    });
+
+    it("keeps raised sol in the presale until it is finalized", async () => {
+      await setupPresale();
+      const treasury = newWallet(1).publicKey;
+      await setupMultisig(treasury);
+      await investSol(newWallet(10), 1e9);
+      const raised = (await program.account.presaleInfo.fetch(presalePda)).solAmountRaised.toNumber();
+
+      const proposal = await proposeSolWithdrawal(treasury, raised);
+      await expectError(executeSolWithdrawal(proposal, treasury), "PresaleNotFinalized");
+
+      await warpTo(END);
+      await program.methods.finalizePresale().accounts(adminContext()).rpc();
+      const before = await solBalance(treasury);
+      await executeSolWithdrawal(proposal, treasury);
+      assert.equal(await solBalance(treasury), before + raised);
+      assert.equal((await program.account.presaleInfo.fetch(presalePda)).solWithdrawn.toNumber(), raised);
+    });
+
+    it("withdraws sol and usdc once enough approvers signed off", async () => {
+      await setupPresale();
+      await addUsdcPaymentMint();
+      const approver = newWallet(1);
+      const treasury = newWallet(1).publicKey;
+      const approversAction = await queueAction(setApproversAction([authority.publicKey, approver.publicKey], 2));
+      const treasuryAction = await queueAction({ addTreasuryWallet: { wallet: treasury } });
+      await waitForTimelock();
+      await setApprovers(approversAction);
+      await addTreasuryWallet(treasuryAction, treasury);
+
+      const buyer = newWallet(10);
+      await fundTokens(usdc, buyer.publicKey, 500e6);
+      await investSol(buyer, 1e9);
+      await investUsdc(buyer, 500e6);
+      await warpTo(END);
+      await program.methods.finalizePresale().accounts(adminContext()).rpc();
+
+      const approve = (proposal: anchor.web3.PublicKey) =>
+        program.methods
+          .approveWithdrawal()
+          .accounts({ signer: approver.publicKey, multisig: multisigPda(), proposal })
+          .signers([approver])
+          .rpc();
+
+      // the proposer's approval alone is not enough
+      const proposal = await proposeSolWithdrawal(treasury, 1e9);
+      await expectError(executeSolWithdrawal(proposal, treasury), "NotEnoughApprovals");
+      await approve(proposal);
+      const before = await solBalance(treasury);
+      await executeSolWithdrawal(proposal, treasury);
+      assert.equal(await solBalance(treasury), before + 1e9);
+
+      // usdc goes to the destination's token account
+      await fundTokens(usdc, treasury, 0);
+      const usdcProposal = await proposeWithdrawal(usdc, treasury, 500e6);
+      await approve(usdcProposal);
+      await program.methods
+        .executeWithdrawal()
+        .accounts({
+          signer: authority.publicKey,
+          presale: presalePda,
+          multisig: multisigPda(),
+          proposal: usdcProposal,
+          destination: treasury,
+          treasuryWallet: treasuryWalletPda(treasury),
+          paymentTokenMint: usdc,
+          presalePaymentAccount: ata(usdc, presalePda),
+          destinationTokenAccount: ata(usdc, treasury),
+        })
+        .rpc();
+      assert.equal(await tokenBalance(ata(usdc, treasury)), 500e6);
+      assert.equal((await program.account.presaleInfo.fetch(presalePda)).usdcWithdrawn.toNumber(), 500e6);
+    });
+  });
+
+  const setTokenDelivery = (deferredDelivery: boolean, tgeTime: number) =>
+    program.methods.setTokenDelivery(deferredDelivery, new BN(tgeTime)).accounts(adminContext()).rpc();
+
+  const claimContext = (buyer: anchor.web3.Keypair) => ({
+    data: dataPda(buyer.publicKey),
+    presale: presalePda,
+    signer: buyer.publicKey,
+    tokenMint: token,
+    presaleTokenAccount: ata(token, presalePda),
+    signerTokenAccount: ata(token, buyer.publicKey),
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: anchor.web3.SystemProgram.programId,
+    associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+  });
+
+  describe("token delivery", () => {
+    const TGE = START + 1000;
+
+    it("fixes the TGE time once it is set", async () => {
+      await setupPresale();
+      await expectError(setTokenDelivery(true, START - 1), "WrongTime");
+      await expectError(setTokenDelivery(true, 0), "WrongTime");
+
+      await setTokenDelivery(true, TGE);
+      await expectError(setTokenDelivery(true, TGE + 1000), "TgeLocked");
+      await expectError(setTokenDelivery(false, 0), "TgeLocked");
+      // the delivery mode can still change before TGE
+      await setTokenDelivery(false, TGE);
+      assert.isFalse((await program.account.presaleInfo.fetch(presalePda)).deferredDelivery);
+
+      await warpTo(TGE);
+      await expectError(setTokenDelivery(true, TGE), "WrongTime");
+    });
+
+    it("delivers credited tokens after TGE", async () => {
+      await setupPresale();
+      await setTokenDelivery(true, TGE);
+      const buyer = newWallet(10);
+      await investSol(buyer, 1e9);
+      const credited = (await program.account.investmentData.fetch(dataPda(buyer.publicKey))).numberOfTokens.toNumber();
+      assert.isAbove(credited, 0);
+      assert.equal(await tokenBalance(ata(token, buyer.publicKey)), 0);
+
+      const claim = () => program.methods.claimTokens().accounts(claimContext(buyer)).signers([buyer]).rpc();
+      await expectError(claim(), "ClaimLocked");
+
+      await warpTo(TGE);
+      await claim();
+      assert.equal(await tokenBalance(ata(token, buyer.publicKey)), credited);
+      await warpTo(TGE + 1);
+      await expectError(claim(), "ZeroAmount");
+    });
+
+    it("keeps credited tokens in the presale for withdrawals", async () => {
+      await setupPresale();
+      await setTokenDelivery(true, TGE);
+      const treasury = anchor.web3.Keypair.generate().publicKey;
+      await setupMultisig(treasury);
+      await fundTokens(token, treasury, 0);
+      await program.methods
+        .grantRole({ treasurer: {} }, authority.publicKey)
+        .accounts({ signer: authority.publicKey, presale: presalePda, roles: rolesPda() })
+        .rpc();
+      const buyer = newWallet(10);
+      await investSol(buyer, 1e9);
+      const credited = (await program.account.investmentData.fetch(dataPda(buyer.publicKey))).numberOfTokens.toNumber();
+
+      const withdraw = (amount: number) =>
+        program.methods
+          .adminWithdrawTokens(new BN(amount))
+          .accounts({
+            signer: authority.publicKey,
+            presale: presalePda,
+            roles: rolesPda(),
+            tokenMint: token,
+            presaleTokenAccount: ata(token, presalePda),
+            destination: treasury,
+            destinationTokenAccount: ata(token, treasury),
+            treasuryWallet: treasuryWalletPda(treasury),
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+      await expectError(withdraw(1), "PresaleNotEnded");
+
+      // the tokens credited to the buyer can't be withdrawn
+      await warpTo(END);
+      const presaleTokens = await tokenBalance(ata(token, presalePda));
+      await expectError(withdraw(presaleTokens), "InsufficientFunds");
+      await withdraw(presaleTokens - credited);
+      assert.equal(await tokenBalance(ata(token, treasury)), presaleTokens - credited);
+      await warpTo(END + 1);
+      await expectError(withdraw(1), "InsufficientFunds");
+
+      await program.methods.claimTokens().accounts(claimContext(buyer)).signers([buyer]).rpc();
+      assert.equal(await tokenBalance(ata(token, buyer.publicKey)), credited);
+    });
+
+    it("doesn't withdraw the sale token through the multisig", async () => {
+      await setupPresale();
+      await setTokenDelivery(true, TGE);
+      const treasury = anchor.web3.Keypair.generate().publicKey;
+      await setupMultisig(treasury);
+      await investSol(newWallet(10), 1e9);
+      await warpTo(END);
+      await program.methods.finalizePresale().accounts(adminContext()).rpc();
+      const presaleTokens = await tokenBalance(ata(token, presalePda));
+
+      const tokenPaymentMint = pda(Buffer.from(PAYMENT_MINT_SEED), token.toBuffer());
+      await expectError(proposeWithdrawal(token, treasury, presaleTokens), "InvalidPaymentToken");
+      await expectError(proposeWithdrawal(token, treasury, presaleTokens, tokenPaymentMint), "AccountNotInitialized");
+      // nor can it be registered as a payment mint to get around that
+      await expectError(
+        program.methods
+          .addPaymentMint(true, anchor.web3.PublicKey.default, new BN(0), new BN(0))
+          .accounts({
+            signer: authority.publicKey,
+            presale: presalePda,
+            paymentMint: tokenPaymentMint,
+            paymentTokenMint: token,
+            presalePaymentAccount: ata(token, presalePda),
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+          })
+          .rpc(),
+        "InvalidPaymentToken"
+      );
+      assert.equal(await tokenBalance(ata(token, presalePda)), presaleTokens);
+    });
+  });
+
+  const stakingDataPda = (owner: anchor.web3.PublicKey) => pda(Buffer.from(STAKING_DATA_SEED), owner.toBuffer());
+
+  const buyAndStake = (buyer: anchor.web3.Keypair, lamports: number) =>
+    program.methods
+      .buyAndStake(new BN(lamports), new BN(0), [], new BN(0))
+      .accounts({
+        investmentData: dataPda(buyer.publicKey),
+        stakingData: stakingDataPda(buyer.publicKey),
+        presale: presalePda,
+        staking: stakingPda,
+        rewardSchedule: pda(Buffer.from(REWARD_SCHEDULE_SEED)),
+        from: buyer.publicKey,
+        signer: buyer.publicKey,
+        tokenMint: token,
+        presaleTokenAccount: ata(token, presalePda),
+        stakingTokenAccount: ata(token, stakingPda),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+      })
+      .signers([buyer])
+      .rpc();
+
+  const stake = (staker: anchor.web3.Keypair, amount: number | bigint) =>
+    program.methods
+      .stake(new BN(amount.toString()))
+      .accounts({
+        stakingData: stakingDataPda(staker.publicKey),
+        from: staker.publicKey,
+        staking: stakingPda,
+        rewardSchedule: pda(Buffer.from(REWARD_SCHEDULE_SEED)),
+        stakingTokenAccount: ata(token, stakingPda),
+        signerTokenAccount: ata(token, staker.publicKey),
+        signer: staker.publicKey,
+        tokenMint: token,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+      })
+      .signers([staker])
+      .rpc();
+
+  const unstakeContext = (staker: anchor.web3.Keypair) => ({
+    stakingData: stakingDataPda(staker.publicKey),
+    from: staker.publicKey,
+    staking: stakingPda,
+    rewardSchedule: pda(Buffer.from(REWARD_SCHEDULE_SEED)),
+    presale: presalePda,
+    stakingTokenAccount: ata(token, stakingPda),
+    signerTokenAccount: ata(token, staker.publicKey),
+    signer: staker.publicKey,
+    tokenMint: token,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: anchor.web3.SystemProgram.programId,
+    associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+  });
+
+  const allowClaiming = () =>
+    program.methods
+      .allowClaiming(true)
+      .accounts({ signer: authority.publicKey, presale: presalePda, staking: stakingPda, roles: rolesPda() })
+      .rpc();
+
+  describe("vesting", () => {
+    const TGE = START + 1000;
+    const CLIFF = 100;
+    const VESTING = 1000;
+
+    // 20% unlock at TGE, the rest vests over 1000 seconds after a 100 second cliff
+    const setupVesting = async () => {
+      await setupPresale();
+      await program.methods.setVesting(2000, new BN(CLIFF), new BN(VESTING)).accounts(adminContext()).rpc();
+      await setTokenDelivery(true, TGE);
+    };
+
+    it("releases tokens at TGE, after the cliff and linearly", async () => {
+      await setupVesting();
+      const buyer = newWallet(10);
+      await investSol(buyer, 1e9);
+      const total = (await program.account.investmentData.fetch(dataPda(buyer.publicKey))).numberOfTokens.toNumber();
+      const tgeUnlock = Math.floor((total * 2000) / 10000);
+      const claim = () => program.methods.claimVested().accounts(claimContext(buyer)).signers([buyer]).rpc();
+      const balance = () => tokenBalance(ata(token, buyer.publicKey));
+
+      await expectError(claim(), "NothingVested");
+
+      await warpTo(TGE);
+      await claim();
+      assert.equal(await balance(), tgeUnlock);
+
+      // nothing more unlocks during the cliff
+      await warpTo(TGE + CLIFF - 1);
+      await expectError(claim(), "NothingVested");
+
+      // halfway through the vesting
+      await warpTo(TGE + CLIFF + VESTING / 2);
+      await claim();
+      assert.equal(await balance(), tgeUnlock + Math.floor(((total - tgeUnlock) * (VESTING / 2)) / VESTING));
+
+      await warpTo(TGE + CLIFF + VESTING);
+      await claim();
+      assert.equal(await balance(), total);
+      await warpTo(TGE + CLIFF + VESTING + 1);
+      await expectError(claim(), "NothingVested");
+    });
+
+    it("keeps staked presale tokens locked until they vest", async () => {
+      await setupVesting();
+      await allowClaiming();
+      const buyer = newWallet(10);
+      await buyAndStake(buyer, 1e9);
+      await fundTokens(token, buyer.publicKey, 0);
+      const staked = (await program.account.stakingData.fetch(stakingDataPda(buyer.publicKey))).presaleTokens.toNumber();
+      const tgeUnlock = Math.floor((staked * 2000) / 10000);
+      const unstake = (amount: number) =>
+        program.methods.unstake(new BN(amount)).accounts(unstakeContext(buyer)).signers([buyer]).rpc();
+      const unstakeAll = () =>
+        program.methods.unstakeAndClaimRewards().accounts(unstakeContext(buyer)).signers([buyer]).rpc();
+
+      await expectError(unstake(1), "TokensLocked");
+      await expectError(unstakeAll(), "TokensLocked");
+
+      // the TGE unlock can be unstaked, the rest is still vesting
+      await warpTo(TGE);
+      await expectError(unstake(tgeUnlock + 1), "TokensLocked");
+      await unstake(tgeUnlock);
+      await expectError(unstakeAll(), "TokensLocked");
+
+      await warpTo(TGE + CLIFF + VESTING);
+      await unstakeAll();
+      const stakingData = await program.account.stakingData.fetch(stakingDataPda(buyer.publicKey));
+      assert.equal(stakingData.totalStakingBalance.toNumber(), 0);
+      assert.isAtLeast(await tokenBalance(ata(token, buyer.publicKey)), staked);
+    });
+  });
+
+  // whitelist leaf of `buyer`, keccak256(buyer || allocation as little endian u64)
+  const whitelistLeaf = (buyer: anchor.web3.PublicKey, allocation: number) =>
+    Buffer.from(keccak_256(Buffer.concat([buyer.toBuffer(), new BN(allocation).toArrayLike(Buffer, "le", 8)])));
+
+  // parent of two nodes, hashed in sorted order
+  const hashPair = (a: Buffer, b: Buffer) =>
+    Buffer.from(keccak_256(Buffer.compare(a, b) <= 0 ? Buffer.concat([a, b]) : Buffer.concat([b, a])));
+
+  const investWhitelisted = (buyer: anchor.web3.Keypair, lamports: number, proof: Buffer[], allocation: number) =>
+    program.methods
+      .invest(new BN(lamports), new BN(0), proof.map((node) => [...node]), new BN(allocation))
+      .accounts(investContext(buyer))
+      .signers([buyer])
+      .rpc();
+
+  describe("whitelist", () => {
+    const WHITELIST_END = START + 100;
+
+    // `limited` may buy 2000 tokens during the whitelist phase, `unlimited` has no allocation
+    const setupWhitelist = async () => {
+      await setupPresale();
+      const limited = newWallet(10);
+      const unlimited = newWallet(10);
+      const limitedLeaf = whitelistLeaf(limited.publicKey, 2000e5);
+      const unlimitedLeaf = whitelistLeaf(unlimited.publicKey, 0);
+      await program.methods
+        .setWhitelist([...hashPair(limitedLeaf, unlimitedLeaf)], new BN(WHITELIST_END))
+        .accounts(adminContext())
+        .rpc();
+      return { limited, unlimited, limitedProof: [unlimitedLeaf], unlimitedProof: [limitedLeaf] };
+    };
+
+    it("only lets whitelisted wallets buy with a valid proof", async () => {
+      const { limited, unlimited, limitedProof, unlimitedProof } = await setupWhitelist();
+      const outsider = newWallet(10);
+
+      await expectError(investWhitelisted(outsider, 0.5e9, [], 0), "NotWhitelisted");
+      await expectError(investWhitelisted(outsider, 0.5e9, unlimitedProof, 0), "NotWhitelisted");
+      // a proof only holds for the wallet's own allocation
+      await expectError(investWhitelisted(limited, 0.5e9, limitedProof, 3000e5), "NotWhitelisted");
+      await expectError(investWhitelisted(limited, 0.5e9, unlimitedProof, 2000e5), "NotWhitelisted");
+
+      await investWhitelisted(limited, 0.5e9, limitedProof, 2000e5);
+      await investWhitelisted(unlimited, 1e9, unlimitedProof, 0);
+      assert.isAbove(await tokenBalance(ata(token, limited.publicKey)), 0);
+      assert.isAbove(await tokenBalance(ata(token, unlimited.publicKey)), 0);
+    });
+
+    it("limits purchases to the allocation", async () => {
+      const { limited, limitedProof } = await setupWhitelist();
+
+      // 0.5 sol buys about 1356 tokens, a second purchase would go over 2000
+      await investWhitelisted(limited, 0.5e9, limitedProof, 2000e5);
+      await expectError(investWhitelisted(limited, 0.6e9, limitedProof, 2000e5), "WhitelistAllocationExceeded");
+      const bought = await tokenBalance(ata(token, limited.publicKey));
+      assert.isAtMost(bought, 2000e5);
+    });
+
+    it("opens the sale to everyone when the whitelist phase ends", async () => {
+      const { limited, limitedProof } = await setupWhitelist();
+      const outsider = newWallet(10);
+      await investWhitelisted(limited, 0.5e9, limitedProof, 2000e5);
+
+      await warpTo(WHITELIST_END);
+      await investWhitelisted(outsider, 0.5e9, [], 0);
+      // the allocation no longer applies
+      await investWhitelisted(limited, 1e9, [], 0);
+      assert.isAbove(await tokenBalance(ata(token, limited.publicKey)), 2000e5);
+    });
+  });
+
+  // accepts usdc as a usd pegged payment mint without its own limits
+  const addUsdcPaymentMint = () =>
+    program.methods
+      .addPaymentMint(true, anchor.web3.PublicKey.default, new BN(0), new BN(0))
+      .accounts({
+        signer: authority.publicKey,
+        presale: presalePda,
+        paymentMint: pda(Buffer.from(PAYMENT_MINT_SEED), usdc.toBuffer()),
+        paymentTokenMint: usdc,
+        presalePaymentAccount: ata(usdc, presalePda),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+      })
+      .rpc();
+
+  const investUsdc = (buyer: anchor.web3.Keypair, amount: number) =>
+    program.methods
+      .invest(new BN(amount), new BN(0), [], new BN(0))
+      .accounts(
+        investContext(buyer, {
+          paymentMint: pda(Buffer.from(PAYMENT_MINT_SEED), usdc.toBuffer()),
+          paymentData: pda(Buffer.from(PAYMENT_DATA_SEED), usdc.toBuffer(), buyer.publicKey.toBuffer()),
+          paymentTokenMint: usdc,
+          presalePaymentAccount: ata(usdc, presalePda),
+          signerPaymentAccount: ata(usdc, buyer.publicKey),
+        })
+      )
+      .signers([buyer])
+      .rpc();
+
+  const claimPaymentRefund = (buyer: anchor.web3.Keypair, mint: anchor.web3.PublicKey) =>
+    program.methods
+      .claimPaymentRefund()
+      .accounts({
+        data: dataPda(buyer.publicKey),
+        paymentData: pda(Buffer.from(PAYMENT_DATA_SEED), mint.toBuffer(), buyer.publicKey.toBuffer()),
+        presale: presalePda,
+        signer: buyer.publicKey,
+        paymentTokenMint: mint,
+        presalePaymentAccount: ata(mint, presalePda),
+        signerPaymentAccount: ata(mint, buyer.publicKey),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+      })
+      .signers([buyer])
+      .rpc();
+
+  const updatePaymentMintAction = (
+    mint: anchor.web3.PublicKey,
+    usdPegged: boolean,
+    priceFeed: anchor.web3.PublicKey,
+    minInvestment: number,
+    maxInvestment: number,
+    isActive: boolean
+  ) => ({
+    updatePaymentMint: {
+      mint,
+      usdPegged,
+      priceFeed,
+      minInvestment: new BN(minInvestment),
+      maxInvestment: new BN(maxInvestment),
+      isActive,
+    },
+  });
+
+  const updatePaymentMint = (timelockAction: anchor.web3.PublicKey, mint: anchor.web3.PublicKey) =>
+    program.methods
+      .updatePaymentMint()
+      .accounts({
+        signer: authority.publicKey,
+        presale: presalePda,
+        timelockAction,
+        paymentMint: pda(Buffer.from(PAYMENT_MINT_SEED), mint.toBuffer()),
+      })
+      .rpc();
+
+  const setInvestmentLimits = (minSol: number, maxSol: number, minUsdc: number, maxUsdc: number) =>
+    program.methods
+      .setInvestmentLimits(new BN(minSol), new BN(maxSol), new BN(minUsdc), new BN(maxUsdc))
+      .accounts(adminContext())
+      .rpc();
+
+  describe("investment limits", () => {
+    it("rejects a min above the max", async () => {
+      await setupPresale();
+      await expectError(setInvestmentLimits(2e9, 1e9, 100e6, 1000e6), "WrongAmount");
+      await expectError(setInvestmentLimits(0.5e9, 1e9, 2000e6, 1000e6), "WrongAmount");
+      // a max of 0 has no limit
+      await setInvestmentLimits(2e9, 0, 2000e6, 0);
+    });
+
+    it("applies the max to all purchases of a wallet together", async () => {
+      await setupPresale();
+      await addUsdcPaymentMint();
+      await setInvestmentLimits(0.5e9, 1e9, 100e6, 1000e6);
+      const buyer = newWallet(10);
+      await fundTokens(usdc, buyer.publicKey, 10_000e6);
+
+      await expectError(investSol(buyer, 0.4e9), "WrongAmount");
+      await investSol(buyer, 0.6e9);
+      // each purchase is within the max, together they are not
+      await expectError(investSol(buyer, 0.5e9), "InvestmentLimitExceeded");
+
+      // 60% of the sol limit is used, which leaves 40% of the usdc limit
+      await expectError(investUsdc(buyer, 500e6), "InvestmentLimitExceeded");
+      await investUsdc(buyer, 400e6);
+      await expectError(investUsdc(buyer, 100e6), "InvestmentLimitExceeded");
+
+      const data = await program.account.investmentData.fetch(dataPda(buyer.publicKey));
+      assert.equal(data.solInvestmentAmount.toNumber(), 0.6e9);
+      assert.equal(data.usdcInvestmentAmount.toNumber(), 400e6);
+
+      // raising the max lets the wallet buy again
+      await setInvestmentLimits(0.5e9, 2e9, 100e6, 2000e6);
+      await warpTo(START + 1);
+      await investSol(buyer, 0.5e9);
+      assert.equal((await program.account.investmentData.fetch(dataPda(buyer.publicKey))).solInvestmentAmount.toNumber(), 1.1e9);
+    });
+  });
+
+  // pyth v2 price account with a sol/usd price of `price` with expo -8, written straight into the bank
+  const setPriceFeed = (
+    address: anchor.web3.PublicKey,
+    publishTime: number,
+    price: number,
+    conf: number,
+    owner: anchor.web3.PublicKey = PYTH_ORACLE
+  ) => {
+    const data = Buffer.alloc(3312);
+    data.writeUInt32LE(0xa1b2c3d4, 0); // magic
+    data.writeUInt32LE(2, 4); // version
+    data.writeUInt32LE(3, 8); // price account
+    data.writeUInt32LE(data.length, 12);
+    data.writeInt32LE(-8, 20); // expo
+    data.writeBigInt64LE(BigInt(publishTime), 96);
+    data.writeBigInt64LE(BigInt(price), 208);
+    data.writeBigUInt64LE(BigInt(conf), 216);
+    data.writeUInt32LE(1, 224); // trading
+    context.setAccount(address, {
+      lamports: 24_000_000,
+      data,
+      owner,
+      executable: false,
+    });
+  };
+
+  const setPriceFeedAction = (priceFeed: anchor.web3.PublicKey, maxPriceAge: number, maxConfidenceBps: number) =>
+    queueAction({ setPriceFeed: { priceFeed, maxPriceAge: new BN(maxPriceAge), maxConfidenceBps } });
+
+  describe("oracle pricing", () => {
+    const SOL_USD = 150e8; // $150
+    const MAX_PRICE_AGE = 60;
+    const MAX_CONFIDENCE_BPS = 100;
+    const ORACLE = START + 2 * 24 * 60 * 60; // the feed is set after the timelock delay
+
+    const setupOracle = async () => {
+      await setupPresale();
+      const priceFeed = anchor.web3.Keypair.generate().publicKey;
+      setPriceFeed(priceFeed, ORACLE, SOL_USD, SOL_USD / 200);
+      const timelockAction = await setPriceFeedAction(priceFeed, MAX_PRICE_AGE, MAX_CONFIDENCE_BPS);
+      await waitForTimelock();
+      await executeAction(timelockAction);
+      return priceFeed;
+    };
+
+    it("prices sol at a fresh feed price", async () => {
+      const priceFeed = await setupOracle();
+      const buyer = newWallet(10);
+
+      await warpTo(ORACLE + MAX_PRICE_AGE);
+      await investSol(buyer, 1e9, { priceFeed });
+      const lamportsPerToken = Math.ceil((USDC_PRICE * 1e11) / SOL_USD);
+      assert.equal(
+        (await program.account.investmentData.fetch(dataPda(buyer.publicKey))).numberOfTokens.toNumber(),
+        Math.floor((1e9 * 1e5) / lamportsPerToken)
+      );
+
+      // only the configured feed is accepted
+      const otherFeed = anchor.web3.Keypair.generate().publicKey;
+      setPriceFeed(otherFeed, ORACLE + MAX_PRICE_AGE, SOL_USD, 0);
+      await expectError(investSol(buyer, 1e9, { priceFeed: otherFeed }), "InvalidPriceFeed");
+
+      // the feed has to be owned by the pyth oracle
+      setPriceFeed(priceFeed, ORACLE + MAX_PRICE_AGE, SOL_USD, 0, anchor.web3.SystemProgram.programId);
+      await expectError(investSol(buyer, 1e9, { priceFeed }), "InvalidPriceFeed");
+    });
+
+    it("changes the feed only after the timelock delay", async () => {
+      await setupPresale();
+      const priceFeed = anchor.web3.Keypair.generate().publicKey;
+      await expectError(setPriceFeedAction(priceFeed, MAX_PRICE_AGE, 10_001), "InvalidPriceFeed");
+
+      const timelockAction = await setPriceFeedAction(priceFeed, MAX_PRICE_AGE, MAX_CONFIDENCE_BPS);
+      await expectError(executeAction(timelockAction), "TimelockNotExpired");
+      // sol keeps its fixed price until then
+      assert.isTrue((await program.account.presaleInfo.fetch(presalePda)).priceFeed.equals(anchor.web3.PublicKey.default));
+      await investSol(newWallet(10), 1e9);
+
+      await waitForTimelock();
+      await executeAction(timelockAction);
+      assert.isTrue((await program.account.presaleInfo.fetch(presalePda)).priceFeed.equals(priceFeed));
+    });
+
+    it("rejects a stale price", async () => {
+      const priceFeed = await setupOracle();
+      const buyer = newWallet(10);
+
+      await warpTo(ORACLE + MAX_PRICE_AGE + 1);
+      await expectError(investSol(buyer, 1e9, { priceFeed }), "StalePrice");
+
+      // a new price update makes the feed usable again
+      setPriceFeed(priceFeed, ORACLE + MAX_PRICE_AGE + 1, SOL_USD, SOL_USD / 200);
+      await warpTo(ORACLE + MAX_PRICE_AGE + 2);
+      await investSol(buyer, 1e9, { priceFeed });
+    });
+
+    it("rejects a price with a wide confidence interval", async () => {
+      const priceFeed = await setupOracle();
+      const buyer = newWallet(10);
+
+      // 2% of the price against a max of 1%
+      setPriceFeed(priceFeed, ORACLE, SOL_USD, SOL_USD / 50);
+      await warpTo(ORACLE + 1);
+      await expectError(investSol(buyer, 1e9, { priceFeed }), "PriceConfidenceTooWide");
+
+      // exactly at the max is accepted
+      setPriceFeed(priceFeed, ORACLE, SOL_USD, SOL_USD / 100);
+      await warpTo(ORACLE + 2);
+      await investSol(buyer, 1e9, { priceFeed });
+    });
+  });
+
+  describe("payment mints", () => {
+    const addPaymentMint = (mint: anchor.web3.PublicKey, usdPegged: boolean, priceFeed: anchor.web3.PublicKey) =>
+      program.methods
+        .addPaymentMint(usdPegged, priceFeed, new BN(0), new BN(0))
+        .accounts({
+          signer: authority.publicKey,
+          presale: presalePda,
+          paymentMint: pda(Buffer.from(PAYMENT_MINT_SEED), mint.toBuffer()),
+          paymentTokenMint: mint,
+          presalePaymentAccount: ata(mint, presalePda),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+        })
+        .rpc();
+
+    const investWithMint = (
+      buyer: anchor.web3.Keypair,
+      mint: anchor.web3.PublicKey,
+      amount: number,
+      priceFeed: anchor.web3.PublicKey | null
+    ) =>
+      program.methods
+        .invest(new BN(amount), new BN(0), [], new BN(0))
+        .accounts(
+          investContext(buyer, {
+            priceFeed,
+            paymentMint: pda(Buffer.from(PAYMENT_MINT_SEED), mint.toBuffer()),
+            paymentData: pda(Buffer.from(PAYMENT_DATA_SEED), mint.toBuffer(), buyer.publicKey.toBuffer()),
+            paymentTokenMint: mint,
+            presalePaymentAccount: ata(mint, presalePda),
+            signerPaymentAccount: ata(mint, buyer.publicKey),
+          })
+        )
+        .signers([buyer])
+        .rpc();
+
+    it("prices and limits wrapped sol like sol", async () => {
+      await setupPresale();
+      await setCaps(0, 0, 0, 100_000e5);
+      if ((await context.banksClient.getAccount(NATIVE_MINT)) === null) {
+        const data = Buffer.alloc(MINT_SIZE);
+        MintLayout.encode(
+          {
+            mintAuthorityOption: 0,
+            mintAuthority: anchor.web3.PublicKey.default,
+            supply: BigInt(0),
+            decimals: 9,
+            isInitialized: true,
+            freezeAuthorityOption: 0,
+            freezeAuthority: anchor.web3.PublicKey.default,
+          },
+          data
+        );
+        context.setAccount(NATIVE_MINT, { lamports: 1e9, data, owner: TOKEN_PROGRAM_ID, executable: false });
+      }
+      // it follows the sol price, not a usd peg or a feed of its own
+      await expectError(addPaymentMint(NATIVE_MINT, false, anchor.web3.Keypair.generate().publicKey), "WrappedSolPayment");
+      await expectError(addPaymentMint(NATIVE_MINT, true, anchor.web3.PublicKey.default), "WrappedSolPayment");
+      await addPaymentMint(NATIVE_MINT, false, anchor.web3.PublicKey.default);
+      await setInvestmentLimits(0.5e9, 1e9, 100e6, 1000e6);
+
+      const buyer = newWallet(10);
+      const wrapped = ata(NATIVE_MINT, buyer.publicKey);
+      await send(
+        [
+          createAssociatedTokenAccountIdempotentInstruction(authority.publicKey, wrapped, buyer.publicKey, NATIVE_MINT),
+          anchor.web3.SystemProgram.transfer({ fromPubkey: buyer.publicKey, toPubkey: wrapped, lamports: 2e9 }),
+          createSyncNativeInstruction(wrapped),
+        ],
+        [buyer]
+      );
+
+      await expectError(investWithMint(buyer, NATIVE_MINT, 0.4e9, null), "WrongAmount");
+      await investWithMint(buyer, NATIVE_MINT, 0.6e9, null);
+      assert.equal(await tokenBalance(ata(token, buyer.publicKey)), Math.floor((0.6e9 * 1e5) / SOL_PRICE));
+      assert.equal((await program.account.presaleInfo.fetch(presalePda)).solAmountRaised.toNumber(), 0.6e9);
+      // wrapped and native sol share the sol limit
+      await expectError(investSol(buyer, 0.5e9), "InvestmentLimitExceeded");
+      await investSol(buyer, 0.4e9);
+      assert.equal((await program.account.investmentData.fetch(dataPda(buyer.publicKey))).solInvestmentAmount.toNumber(), 1e9);
+
+      // a refund pays the native sol back in lamports and the wrapped sol in tokens
+      await warpTo(END);
+      await program.methods.finalizePresale().accounts(adminContext()).rpc();
+      const before = await solBalance(buyer.publicKey);
+      await claimRefund(buyer);
+      assert.equal(await solBalance(buyer.publicKey), before + 0.4e9);
+      const wrappedBefore = await tokenBalance(wrapped);
+      await claimPaymentRefund(buyer, NATIVE_MINT);
+      assert.equal(await tokenBalance(wrapped), wrappedBefore + 0.6e9);
+    });
+
+    it("prices other mints at their own feed", async () => {
+      await setupPresale();
+      // feeds are held to the default staleness and confidence limits, sol keeps its fixed price
+      const mint = await createMint(8);
+      const mintFeed = anchor.web3.Keypair.generate().publicKey;
+      const mintUsd = 10e8; // $10 with expo -8
+      setPriceFeed(mintFeed, START, mintUsd, 0);
+
+      await expectError(addPaymentMint(mint, false, anchor.web3.PublicKey.default), "InvalidPriceFeed");
+      await addPaymentMint(mint, false, mintFeed);
+      const buyer = newWallet(10);
+      await fundTokens(mint, buyer.publicKey, 1000e8);
+
+      await expectError(investWithMint(buyer, mint, 100e8, null), "InvalidPriceFeed");
+      const otherFeed = anchor.web3.Keypair.generate().publicKey;
+      setPriceFeed(otherFeed, START, mintUsd, 0);
+      await expectError(investWithMint(buyer, mint, 100e8, otherFeed), "InvalidPriceFeed");
+
+      // $1000 at $0.079067 per token
+      await investWithMint(buyer, mint, 100e8, mintFeed);
+      const unitsPerToken = Math.ceil((USDC_PRICE * 1e10) / mintUsd);
+      assert.equal(await tokenBalance(ata(token, buyer.publicKey)), Math.floor((100e8 * 1e5) / unitsPerToken));
+      assert.equal(await tokenBalance(ata(mint, presalePda)), 100e8);
+
+      // the purchase counts at its usd value towards the usd limits and cap
+      assert.equal((await program.account.presaleInfo.fetch(presalePda)).usdcAmountRaised.toNumber(), 1000e6);
+      assert.equal((await program.account.investmentData.fetch(dataPda(buyer.publicKey))).usdcInvestmentAmount.toNumber(), 1000e6);
+      await expectError(investWithMint(buyer, mint, 5e8, mintFeed), "WrongAmount");
+      await setInvestmentLimits(0.5e9, 1e9, 100e6, 1500e6);
+      await expectError(investWithMint(buyer, mint, 60e8, mintFeed), "InvestmentLimitExceeded");
+
+      // the mint's feed is held to the same staleness limit
+      await warpTo(START + 61);
+      await expectError(investWithMint(buyer, mint, 100e8, mintFeed), "StalePrice");
+
+      setPriceFeed(mintFeed, START + 61, mintUsd, 0);
+      await warpTo(START + 62);
+      await setInvestmentLimits(0.5e9, 1e9, 100e6, 0);
+      await setCaps(0, 0, 1500e6, 0);
+      await investWithMint(buyer, mint, 100e8, mintFeed);
+      // only the $500 left under the cap is charged
+      const presaleData = await program.account.presaleInfo.fetch(presalePda);
+      assert.approximately(presaleData.usdcAmountRaised.toNumber(), 1500e6, 1e6);
+      assert.approximately(await tokenBalance(ata(mint, presalePda)), 150e8, 1e6);
+    });
+
+    it("reprices a mint only through a queued action", async () => {
+      await setupPresale();
+      const mint = await createMint(6);
+      const other = await createMint(6);
+      await addPaymentMint(mint, true, anchor.web3.PublicKey.default);
+      await addPaymentMint(other, true, anchor.web3.PublicKey.default);
+
+      await expectError(
+        queueAction(updatePaymentMintAction(mint, false, anchor.web3.PublicKey.default, 0, 0, true)),
+        "InvalidPriceFeed"
+      );
+      await expectError(
+        queueAction(updatePaymentMintAction(mint, true, anchor.web3.PublicKey.default, 200e6, 100e6, true)),
+        "WrongAmount"
+      );
+      const timelockAction = await queueAction(
+        updatePaymentMintAction(mint, true, anchor.web3.PublicKey.default, 100e6, 1000e6, true)
+      );
+      await expectError(updatePaymentMint(timelockAction, mint), "TimelockNotExpired");
+
+      await waitForTimelock();
+      await expectError(executeAction(timelockAction), "ActionMismatch");
+      await expectError(updatePaymentMint(timelockAction, other), "ActionMismatch");
+      await updatePaymentMint(timelockAction, mint);
+      const paymentMint = await program.account.paymentMint.fetch(pda(Buffer.from(PAYMENT_MINT_SEED), mint.toBuffer()));
+      assert.equal(paymentMint.minInvestment.toNumber(), 100e6);
+      assert.equal(paymentMint.maxInvestment.toNumber(), 1000e6);
+    });
+  });
+
+  describe("usdc mint", () => {
+    const setUsdcMint = (mint: anchor.web3.PublicKey, signer: anchor.web3.Keypair = authority) =>
+      program.methods
+        .setUsdcMint()
+        .accounts({
+          signer: signer.publicKey,
+          presale: presalePda,
+          usdcMint: mint,
+          paymentMint: pda(Buffer.from(PAYMENT_MINT_SEED), mint.toBuffer()),
+          presaleUsdcAccount: ata(mint, presalePda),
+          roles: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+        })
+        .signers(signer === authority ? [] : [signer])
+        .rpc();
+
+    it("replaces the usdc mint", async () => {
+      await setupPresale();
+      const newUsdc = await createMint(6);
+
+      await expectError(setUsdcMint(newUsdc, newWallet(1)), "Unauthorized");
+      await expectError(setUsdcMint(token), "InvalidUSDC");
+
+      await setUsdcMint(newUsdc);
+      assert.isTrue((await program.account.presaleInfo.fetch(presalePda)).usdcMint.equals(newUsdc));
+      // the presale can receive the new mint right away
+      assert.isNotNull(await context.banksClient.getAccount(ata(newUsdc, presalePda)));
+    });
+
+    it("rejects a mint registered without the usd peg", async () => {
+      await setupPresale();
+      const mint = await createMint(6);
+      await program.methods
+        .addPaymentMint(false, anchor.web3.Keypair.generate().publicKey, new BN(0), new BN(0))
+        .accounts({
+          signer: authority.publicKey,
+          presale: presalePda,
+          paymentMint: pda(Buffer.from(PAYMENT_MINT_SEED), mint.toBuffer()),
+          paymentTokenMint: mint,
+          presalePaymentAccount: ata(mint, presalePda),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+        })
+        .rpc();
+
+      await expectError(setUsdcMint(mint), "InvalidUSDC");
+      assert.isTrue((await program.account.presaleInfo.fetch(presalePda)).usdcMint.equals(usdc));
+    });
+  });
+
+  describe("referrals", () => {
+    const referralDataPda = (referrer: anchor.web3.PublicKey) => pda(Buffer.from(REFERRAL_SEED), referrer.toBuffer());
+    const investReferred = (buyer: anchor.web3.Keypair, lamports: number, referrer: anchor.web3.PublicKey) =>
+      investSol(buyer, lamports, {
+        referrer,
+        referrerData: dataPda(referrer),
+        referralData: referralDataPda(referrer),
+      });
+    const setReferralBonus = (bps: number) =>
+      program.methods.setReferralBonus(bps).accounts(adminContext()).rpc();
+
+    it("reserves the referral reward against the token cap", async () => {
+      await setupPresale();
+      await setReferralBonus(1000);
+      const referrer = newWallet(10);
+      await investSol(referrer, 1e9);
+      const hardCap = (await program.account.presaleInfo.fetch(presalePda)).totalTokensSold.toNumber() + 1000e5;
+      await setCaps(hardCap, 0, 0, 0);
+
+      // only the share of the 1000 tokens left that leaves room for the 10% reward is sold
+      const buyer = newWallet(100);
+      await investReferred(buyer, 10e9, referrer.publicKey);
+      const bought = Math.floor((1000e5 * 10_000) / 11_000);
+      assert.equal((await program.account.investmentData.fetch(dataPda(buyer.publicKey))).numberOfTokens.toNumber(), bought);
+      const referralData = await program.account.referralData.fetch(referralDataPda(referrer.publicKey));
+      assert.equal(referralData.rewardTokens.toNumber(), Math.floor(bought / 10));
+
+      const presaleData = await program.account.presaleInfo.fetch(presalePda);
+      assert.equal(presaleData.referralRewards.toNumber(), Math.floor(bought / 10));
+      assert.isAtMost(presaleData.totalTokensSold.toNumber() + presaleData.referralRewards.toNumber(), hardCap);
+      await warpTo(START + 1);
+      await expectError(investReferred(buyer, 1e9, referrer.publicKey), "HardCapReached");
+    });
+
+    it("keeps crediting the referrer of a wallet", async () => {
+      await setupPresale();
+      await setReferralBonus(500);
+      const referrer = newWallet(10);
+      const otherReferrer = newWallet(10);
+      await investSol(referrer, 1e9);
+      await investSol(otherReferrer, 1e9);
+
+      const buyer = newWallet(10);
+      await investReferred(buyer, 0.5e9, referrer.publicKey);
+      // the wallet is bound to its first referrer
+      await expectError(investSol(buyer, 0.5e9), "ReferrerRequired");
+      await expectError(investReferred(buyer, 0.5e9, otherReferrer.publicKey), "InvalidReferrer");
+      await investReferred(buyer, 0.6e9, referrer.publicKey);
+
+      const referralData = await program.account.referralData.fetch(referralDataPda(referrer.publicKey));
+      const buyerData = await program.account.investmentData.fetch(dataPda(buyer.publicKey));
+      assert.equal(referralData.referredWallets.toNumber(), 1);
+      assert.equal(referralData.referredSolAmount.toNumber(), 1.1e9);
+      assert.equal(referralData.referredTokens.toNumber(), buyerData.numberOfTokens.toNumber());
+    });
+
+    const claimReferralRewards = (referrer: anchor.web3.Keypair) =>
+      program.methods
+        .claimReferralRewards()
+        .accounts({
+          referralData: referralDataPda(referrer.publicKey),
+          presale: presalePda,
+          signer: referrer.publicKey,
+          tokenMint: token,
+          presaleTokenAccount: ata(token, presalePda),
+          signerTokenAccount: ata(token, referrer.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+        })
+        .signers([referrer])
+        .rpc();
+
+    it("pays the referral reward after a successful finalize", async () => {
+      await setupPresale();
+      await setReferralBonus(1000);
+      await setCaps(0, 0, 0, 1000e5);
+      const referrer = newWallet(10);
+      await investSol(referrer, 1e9);
+      await investReferred(newWallet(10), 1e9, referrer.publicKey);
+      const reward = (await program.account.referralData.fetch(referralDataPda(referrer.publicKey))).rewardTokens.toNumber();
+      assert.isAbove(reward, 0);
+
+      await expectError(claimReferralRewards(referrer), "ClaimLocked");
+      await warpTo(END);
+      await program.methods.finalizePresale().accounts(adminContext()).rpc();
+      assert.isTrue((await program.account.presaleInfo.fetch(presalePda)).isSuccessful);
+
+      const before = await tokenBalance(ata(token, referrer.publicKey));
+      await claimReferralRewards(referrer);
+      assert.equal(await tokenBalance(ata(token, referrer.publicKey)), before + reward);
+      assert.equal((await program.account.referralData.fetch(referralDataPda(referrer.publicKey))).claimedTokens.toNumber(), reward);
+      await warpTo(END + 1);
+      await expectError(claimReferralRewards(referrer), "NoRewards");
+    });
+
+    it("keeps the referral reward locked after a failed finalize", async () => {
+      await setupPresale();
+      await setReferralBonus(1000);
+      await setCaps(0, 0, 0, 100_000e5);
+      const referrer = newWallet(10);
+      await investSol(referrer, 1e9);
+      await investReferred(newWallet(10), 1e9, referrer.publicKey);
+
+      await warpTo(END);
+      await program.methods.finalizePresale().accounts(adminContext()).rpc();
+      assert.isFalse((await program.account.presaleInfo.fetch(presalePda)).isSuccessful);
+      await expectError(claimReferralRewards(referrer), "ClaimLocked");
+    });
+  });
+
+  describe("bonus tiers", () => {
+    const bonusConfigPda = () => pda(Buffer.from(BONUS_SEED));
+    // 20% bonus on purchases of at least 100 tokens
+    const setBonusTiers = () =>
+      program.methods
+        .setBonusTiers([{ minTokens: new BN(100e5), bonusBps: 2000 }], 0, new BN(0))
+        .accounts({
+          signer: authority.publicKey,
+          presale: presalePda,
+          bonusConfig: bonusConfigPda(),
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+
+    it("counts the bonus against the token cap", async () => {
+      await setupPresale();
+      await setBonusTiers();
+      const first = newWallet(10);
+      await investSol(first, 1e9, { bonusConfig: bonusConfigPda() });
+      let presaleData = await program.account.presaleInfo.fetch(presalePda);
+      assert.isAbove(presaleData.totalBonusTokens.toNumber(), 0);
+      const committed = presaleData.totalTokensSold.toNumber() + presaleData.totalBonusTokens.toNumber();
+      const hardCap = committed + 1000e5;
+      await setCaps(hardCap, 0, 0, 0);
+
+      // the bonus has to fit in the 1000 tokens left along with the bought tokens
+      const buyer = newWallet(100);
+      await investSol(buyer, 10e9, { bonusConfig: bonusConfigPda() });
+      const bought = Math.floor((1000e5 * 10_000) / 12_000);
+      const buyerData = await program.account.investmentData.fetch(dataPda(buyer.publicKey));
+      assert.equal(buyerData.bonusTokens.toNumber(), Math.floor(bought / 5));
+      assert.equal(buyerData.numberOfTokens.toNumber(), bought + Math.floor(bought / 5));
+
+      presaleData = await program.account.presaleInfo.fetch(presalePda);
+      assert.isAtMost(presaleData.totalTokensSold.toNumber() + presaleData.totalBonusTokens.toNumber(), hardCap);
+      await warpTo(START + 1);
+      await expectError(investSol(buyer, 1e9, { bonusConfig: bonusConfigPda() }), "HardCapReached");
+    });
+
+    it("counts the bonus against the whitelist allocation", async () => {
+      await setupPresale();
+      await setBonusTiers();
+      const buyer = newWallet(10);
+      // a tree of one leaf, its root is the leaf itself
+      await program.methods
+        .setWhitelist([...whitelistLeaf(buyer.publicKey, 1500e5)], new BN(START + 100))
+        .accounts(adminContext())
+        .rpc();
+      const investWithBonus = () =>
+        program.methods
+          .invest(new BN(0.5e9), new BN(0), [], new BN(1500e5))
+          .accounts(investContext(buyer, { bonusConfig: bonusConfigPda() }))
+          .signers([buyer])
+          .rpc();
+
+      // 0.5 sol buys about 1356 tokens, about 1627 with the bonus
+      await expectError(investWithBonus(), "WhitelistAllocationExceeded");
+      await program.methods
+        .setBonusTiers([], 0, new BN(0))
+        .accounts({
+          signer: authority.publicKey,
+          presale: presalePda,
+          bonusConfig: bonusConfigPda(),
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+      await investWithBonus();
+      assert.isAtMost(await tokenBalance(ata(token, buyer.publicKey)), 1500e5);
+    });
+  });
+
+  describe("roles", () => {
+    it("only lets the granted key act as staking manager", async () => {
+      await setupPresale();
+      const manager = newWallet(1);
+      // the role has one holder, granting it to another key takes it from the authority
+      await program.methods
+        .grantRole({ stakingManager: {} }, manager.publicKey)
+        .accounts({ signer: authority.publicKey, presale: presalePda, roles: rolesPda() })
+        .rpc();
+
+      await expectError(allowClaiming(), "Unauthorized");
+      await program.methods
+        .allowClaiming(true)
+        .accounts({ signer: manager.publicKey, presale: presalePda, staking: stakingPda, roles: rolesPda() })
+        .signers([manager])
+        .rpc();
+      assert.isTrue((await program.account.stakingInfo.fetch(stakingPda)).allowClaiming);
+    });
+  });
+
+  describe("staking rewards", () => {
+    it("rejects a balance whose rewards overflow instead of panicking", async () => {
+      await setupPresale();
+      // a single token unit alone in the pool collects a whole day of rewards
+      const dust = newWallet(1);
+      await fundTokens(token, dust.publicKey, 1);
+      await stake(dust, 1);
+      await warpTo(START + 24 * 60 * 60);
+
+      const whale = newWallet(1);
+      const balance = BigInt("10000000000000000000");
+      await fundTokens(token, whale.publicKey, balance);
+      await expectError(stake(whale, balance), "Overflow");
+
+      // the pool keeps working for everyone else
+      await allowClaiming();
+      await program.methods.claimRewards().accounts(unstakeContext(dust)).signers([dust]).rpc();
+      assert.approximately(await tokenBalance(ata(token, dust.publicKey)), 1000e5, 1);
+    });
+  });
+
+  describe("staking withdrawals", () => {
+    const withdrawStakingTokens = (timelockAction: anchor.web3.PublicKey, destination: anchor.web3.PublicKey) =>
+      program.methods
+        .executeAction()
+        .accounts({
+          signer: authority.publicKey,
+          presale: presalePda,
+          staking: stakingPda,
+          timelockAction,
+          roles: rolesPda(),
+          tokenMint: token,
+          stakingTokenAccount: ata(token, stakingPda),
+          rewardSchedule: pda(Buffer.from(REWARD_SCHEDULE_SEED)),
+          destination,
+          destinationTokenAccount: ata(token, destination),
+          treasuryWallet: treasuryWalletPda(destination),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    it("leaves the stakes and the owed rewards in staking", async () => {
+      await setupPresale();
+      const treasury = anchor.web3.Keypair.generate().publicKey;
+      const outsider = anchor.web3.Keypair.generate().publicKey;
+      await setupMultisig(treasury);
+      await fundTokens(token, treasury, 0);
+      await fundTokens(token, outsider, 0);
+      await program.methods
+        .grantRole({ treasurer: {} }, authority.publicKey)
+        .accounts({ signer: authority.publicKey, presale: presalePda, roles: rolesPda() })
+        .rpc();
+
+      const staker = newWallet(10);
+      await buyAndStake(staker, 1e9);
+      const staked = (await program.account.stakingInfo.fetch(stakingPda)).totalTokensStaked.toNumber();
+      const toOutsider = await queueAction({ withdrawStakingTokens: { destination: outsider } });
+      const toTreasury = await queueAction({ withdrawStakingTokens: { destination: treasury } });
+      // the two daily periods of the timelock delay emit 2000 tokens to the staker
+      await waitForTimelock();
+
+      // only allowlisted treasury wallets receive the tokens
+      await expectError(withdrawStakingTokens(toOutsider, outsider), "AccountNotInitialized");
+      await withdrawStakingTokens(toTreasury, treasury);
+      assert.equal(await tokenBalance(ata(token, treasury)), 100_000e5 - 2000e5);
+      assert.equal(await tokenBalance(ata(token, stakingPda)), staked + 2000e5);
+
+      // the staker can still take out the stake and the rewards
+      await allowClaiming();
+      await program.methods.unstakeAndClaimRewards().accounts(unstakeContext(staker)).signers([staker]).rpc();
+      assert.isAtMost(await tokenBalance(ata(token, stakingPda)), 1);
+      assert.isAtLeast(await tokenBalance(ata(token, staker.publicKey)), staked + 2000e5 - 1);
+    });
+  });
+
+  describe("migration", () => {
+    // the first PresaleInfo layout ended with the authority: discriminator, token mint,
+    // six amounts, is_live, is_initialized and the authority
+    const V1_PRESALE_LEN = 8 + 32 + 6 * 8 + 2 + 32;
+
+    const migratePresale = (signer: anchor.web3.Keypair, usdcMint: anchor.web3.PublicKey) =>
+      program.methods
+        .migratePresale(usdcMint)
+        .accounts({ signer: signer.publicKey, presale: presalePda, systemProgram: anchor.web3.SystemProgram.programId })
+        .signers([signer])
+        .rpc();
+
+    it("migrates an old presale account with the initializer defaults", async () => {
+      await setupPresale();
+      await investSol(newWallet(10), 1e9);
+      const account = await context.banksClient.getAccount(presalePda);
+      context.setAccount(presalePda, {
+        lamports: account.lamports,
+        data: Buffer.from(account.data).subarray(0, V1_PRESALE_LEN),
+        owner: account.owner,
+        executable: false,
+      });
+
+      await expectError(migratePresale(newWallet(1), usdc), "Unauthorized");
+      await migratePresale(authority, usdc);
+      assert.equal((await context.banksClient.getAccount(presalePda)).data.length, account.data.length);
+      const presaleData = await program.account.presaleInfo.fetch(presalePda);
+      assert.isTrue(presaleData.usdcMint.equals(usdc));
+      assert.equal(presaleData.minSolInvestment.toNumber(), 0.5e9);
+      assert.equal(presaleData.maxSolInvestment.toNumber(), 200e9);
+      assert.equal(presaleData.minUsdcInvestment.toNumber(), 100e6);
+      assert.equal(presaleData.maxUsdcInvestment.toNumber(), 40_000e6);
+      assert.equal(presaleData.maxPriceAge.toNumber(), 60);
+      assert.equal(presaleData.maxConfidenceBps, 100);
+      assert.equal(presaleData.timelockDelay.toNumber(), 2 * 24 * 60 * 60);
+      // the old version delivered the sold tokens on purchase
+      assert.isAbove(presaleData.totalTokensSold.toNumber(), 0);
+      assert.equal(presaleData.tokensDelivered.toNumber(), presaleData.totalTokensSold.toNumber());
+
+      // the sale goes on with the migrated account
+      await investSol(newWallet(10), 1e9);
+      await warpTo(START + 1);
+      await expectError(migratePresale(authority, usdc), "AlreadyMigrated");
+    });
+
+    // the first StakingInfo layout ended with allow_claiming, the first StakingData layout
+    // with is_first_time
+    const V1_STAKING_LEN = 8 + 32 + 32 + 3 * 8 + 1;
+    const V1_STAKING_DATA_LEN = 8 + 2 * 8 + 1;
+    const DAY = 24 * 60 * 60;
+
+    const truncate = async (address: anchor.web3.PublicKey, len: number) => {
+      const account = await context.banksClient.getAccount(address);
+      context.setAccount(address, {
+        lamports: account.lamports,
+        data: Buffer.from(account.data).subarray(0, len),
+        owner: account.owner,
+        executable: false,
+      });
+    };
+
+    const migrateStaking = (signer: anchor.web3.Keypair) =>
+      program.methods
+        .migrateStaking()
+        .accounts({
+          signer: signer.publicKey,
+          presale: presalePda,
+          staking: stakingPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([signer])
+        .rpc();
+
+    const claimRewards = async (staker: anchor.web3.Keypair) => {
+      const before = await tokenBalance(ata(token, staker.publicKey));
+      await program.methods.claimRewards().accounts(unstakeContext(staker)).signers([staker]).rpc();
+      return (await tokenBalance(ata(token, staker.publicKey))) - before;
+    };
+
+    it("migrates old staking accounts with the rewards the old version owes", async () => {
+      await setupPresale();
+      const early = newWallet(10);
+      await buyAndStake(early, 1e9);
+      const staked = (await program.account.stakingData.fetch(stakingDataPda(early.publicKey))).totalStakingBalance.toNumber();
+      await truncate(stakingPda, V1_STAKING_LEN);
+      await truncate(stakingDataPda(early.publicKey), V1_STAKING_DATA_LEN);
+
+      // the old version owes the only staker the first month's daily reward for 10 days
+      const legacyRewards = 1205350000 * 10;
+      const legacyBound = 1563693000 * 10;
+      await warpTo(START + 10 * DAY);
+      await expectError(migrateStaking(newWallet(1)), "Unauthorized");
+      await migrateStaking(authority);
+      let stakingData = await program.account.stakingInfo.fetch(stakingPda);
+      assert.equal(stakingData.totalTokensStaked.toNumber(), staked);
+      assert.equal(stakingData.lastRewardTime.toNumber(), START + 10 * DAY);
+      assert.equal(stakingData.legacyRewardsReserved.toNumber(), legacyBound);
+      assert.equal(
+        stakingData.rewardsAccrued.toNumber(),
+        stakingData.totalTokensRewarded.toNumber() + legacyBound
+      );
+      await fundTokens(token, stakingPda, legacyRewards);
+
+      // the early staker earns the first day alone, a second staker only from its deposit on
+      const late = newWallet(1);
+      await warpTo(START + 11 * DAY);
+      await fundTokens(token, late.publicKey, staked);
+      await stake(late, staked);
+
+      // the old position has to be migrated before it can be used
+      await warpTo(START + 12 * DAY);
+      await fundTokens(token, early.publicKey, staked);
+      await expectError(stake(early, staked), "AccountDidNotDeserialize");
+      await program.methods
+        .migrateStakingData()
+        .accounts({
+          signer: early.publicKey,
+          stakingData: stakingDataPda(early.publicKey),
+          staking: stakingPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([early])
+        .rpc();
+      // the last old position is migrated, the rest of the reserve is released
+      stakingData = await program.account.stakingInfo.fetch(stakingPda);
+      assert.equal(stakingData.legacyRewardsReserved.toNumber(), 0);
+      assert.equal(stakingData.legacyUnmigratedStake.toNumber(), 0);
+      assert.equal(stakingData.rewardsAccrued.toNumber(), legacyRewards + 1000e5);
+      assert.approximately(
+        (await program.account.stakingData.fetch(stakingDataPda(early.publicKey))).pendingRewards.toNumber(),
+        legacyRewards + 1000e5,
+        1
+      );
+
+      // the top-up doesn't earn the rewards emitted before it
+      await stake(early, staked);
+      await allowClaiming();
+      assert.approximately(await claimRewards(early), legacyRewards + 1500e5, 2);
+      assert.approximately(await claimRewards(late), 500e5, 2);
+
+      // from now on the early staker holds two thirds of the stakes
+      await warpTo(START + 13 * DAY);
+      assert.approximately(await claimRewards(early), (1000e5 * 2) / 3, 2);
+      assert.approximately(await claimRewards(late), 1000e5 / 3, 2);
+    });
+
+    // the first InvestmentData layout ended with number_of_tokens
+    const V1_INVESTMENT_DATA_LEN = 8 + 3 * 8;
+
+    const migrateInvestmentData = (buyer: anchor.web3.Keypair) =>
+      program.methods
+        .migrateInvestmentData()
+        .accounts({
+          signer: buyer.publicKey,
+          data: dataPda(buyer.publicKey),
+          presale: presalePda,
+          paymentData: pda(Buffer.from(PAYMENT_DATA_SEED), usdc.toBuffer(), buyer.publicKey.toBuffer()),
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+    it("migrates old investment data with the tokens delivered and the usdc paid", async () => {
+      await setupPresale();
+      await addUsdcPaymentMint();
+      await setCaps(0, 0, 0, 100_000e5);
+      const buyer = newWallet(10);
+      await fundTokens(usdc, buyer.publicKey, 500e6);
+      await investSol(buyer, 1e9);
+      await investUsdc(buyer, 500e6);
+      const bought = (await program.account.investmentData.fetch(dataPda(buyer.publicKey))).numberOfTokens.toNumber();
+
+      // the old version recorded neither the delivered tokens nor what was paid per mint
+      const paymentDataPda = pda(Buffer.from(PAYMENT_DATA_SEED), usdc.toBuffer(), buyer.publicKey.toBuffer());
+      await truncate(dataPda(buyer.publicKey), V1_INVESTMENT_DATA_LEN);
+      context.setAccount(paymentDataPda, {
+        lamports: 0,
+        data: Buffer.alloc(0),
+        owner: anchor.web3.SystemProgram.programId,
+        executable: false,
+      });
+
+      await warpTo(END);
+      await program.methods.finalizePresale().accounts(adminContext()).rpc();
+      await expectError(claimRefund(buyer), "AccountDidNotDeserialize");
+
+      await migrateInvestmentData(buyer);
+      const userData = await program.account.investmentData.fetch(dataPda(buyer.publicKey));
+      assert.equal(userData.claimedTokens.toNumber(), bought);
+      assert.equal((await program.account.paymentInvestmentData.fetch(paymentDataPda)).investmentAmount.toNumber(), 500e6);
+      await warpTo(END + 1);
+      await expectError(migrateInvestmentData(buyer), "AlreadyMigrated");
+
+      // the delivered tokens go back before the sol and the usdc are refunded
+      const before = await solBalance(buyer.publicKey);
+      await claimRefund(buyer);
+      assert.equal(await solBalance(buyer.publicKey), before + 1e9);
+      assert.equal(await tokenBalance(ata(token, buyer.publicKey)), 0);
+      await claimPaymentRefund(buyer, usdc);
+      assert.equal(await tokenBalance(ata(usdc, buyer.publicKey)), 500e6);
+    });
+  });
+
+  describe("presale rounds", () => {
+    // rounds are priced at least the default timelock delay before they start
+    const ROUNDS = START + 2 * 24 * 60 * 60;
+
+    const createRound = (index: number, start: number, end: number) =>
+      program.methods
+        .createRound(new BN(start), new BN(end), new BN(100_000e5), new BN(SOL_PRICE), new BN(USDC_PRICE))
+        .accounts({
+          signer: authority.publicKey,
+          presale: presalePda,
+          round: roundPda(index),
+          previousRound: index > 0 ? roundPda(index - 1) : null,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+
+    it("keeps rounds from overlapping", async () => {
+      await setupPresale();
+      await expectError(createRound(0, ROUNDS - 1, ROUNDS + 100), "RoundStartTooSoon");
+      await createRound(0, ROUNDS, ROUNDS + 100);
+
+      // round 1 can't start before round 0 ends
+      await expectError(createRound(1, ROUNDS + 50, ROUNDS + 200), "RoundOverlap");
+      await createRound(1, ROUNDS + 100, ROUNDS + 200);
+      await createRound(2, ROUNDS + 200, ROUNDS + 1000);
+
+      // an edit of round 1 has to end before round 2 starts
+      await expectError(
+        program.methods
+          .updateRound(new BN(ROUNDS + 100), new BN(ROUNDS + 250), new BN(100_000e5), new BN(SOL_PRICE), new BN(USDC_PRICE))
+          .accounts({
+            signer: authority.publicKey,
+            presale: presalePda,
+            round: roundPda(1),
+            previousRound: roundPda(0),
+            nextRound: roundPda(2),
+          })
+          .rpc(),
+        "RoundOverlap"
+      );
+      await program.methods
+        .updateRound(new BN(ROUNDS + 120), new BN(ROUNDS + 200), new BN(100_000e5), new BN(SOL_PRICE), new BN(USDC_PRICE))
+        .accounts({
+          signer: authority.publicKey,
+          presale: presalePda,
+          round: roundPda(1),
+          previousRound: roundPda(0),
+          nextRound: roundPda(2),
+        })
+        .rpc();
+      const round = await program.account.presaleRound.fetch(roundPda(1));
+      assert.equal(round.startTime.toNumber(), ROUNDS + 120);
+
+      // nor can a round be edited within the timelock delay of its start
+      await warpTo(START + 121);
+      await expectError(
+        program.methods
+          .updateRound(new BN(ROUNDS + 150), new BN(ROUNDS + 200), new BN(100_000e5), new BN(SOL_PRICE), new BN(USDC_PRICE))
+          .accounts({
+            signer: authority.publicKey,
+            presale: presalePda,
+            round: roundPda(1),
+            previousRound: roundPda(0),
+            nextRound: roundPda(2),
+          })
+          .rpc(),
+        "RoundStartTooSoon"
+      );
+
+      // the flat prices are unused once rounds exist
+      const presaleData = await program.account.presaleInfo.fetch(presalePda);
+      await expectError(
+        program.methods
+          .queueAction({ changePrice: { solPrice: new BN(1), usdcPrice: new BN(1) } })
+          .accounts({
+            signer: authority.publicKey,
+            presale: presalePda,
+            timelockAction: pda(Buffer.from(ACTION_SEED), presaleData.actionCount.toArrayLike(Buffer, "le", 8)),
+          })
+          .rpc(),
+        "RoundPricesActive"
+      );
+    });
+
+    it("moves past several expired rounds at once", async () => {
+      await setupPresale();
+      await createRound(0, ROUNDS, ROUNDS + 100);
+      await createRound(1, ROUNDS + 100, ROUNDS + 200);
+      await createRound(2, ROUNDS + 200, ROUNDS + 1000);
+      const buyer = newWallet(10);
+
+      await warpTo(ROUNDS + 10);
+      await investSol(buyer, 0.5e9, { round: roundPda(0), nextRound: null });
+      assert.isTrue((await program.account.presaleRound.fetch(roundPda(0))).tokensSold.toNumber() > 0);
+
+      // rounds 0 and 1 both expired, round 1 can't be bought from anymore
+      await warpTo(ROUNDS + 250);
+      await expectError(investSol(buyer, 0.5e9, { round: roundPda(0), nextRound: null }), "RoundEnded");
+      await expectError(investSol(buyer, 0.5e9, { round: roundPda(0), nextRound: roundPda(1) }), "RoundEnded");
+
+      await investSol(buyer, 0.5e9, { round: roundPda(0), nextRound: roundPda(2) });
+      const presaleData = await program.account.presaleInfo.fetch(presalePda);
+      assert.equal(presaleData.currentRound, 2);
+      assert.equal((await program.account.presaleRound.fetch(roundPda(1))).tokensSold.toNumber(), 0);
+      assert.isTrue((await program.account.presaleRound.fetch(roundPda(2))).tokensSold.toNumber() > 0);
+    });
+  });
+});
@@ -19,60 +19,6 @@ let airdropTx = await anchor.getProvider().connection.requestAirdrop(publicKey,
 await confirmTransaction(airdropTx);
 }
 
-type userData = {
-  totalStakingBalance: anchor.BN;
-  stakeDate: anchor.BN;
-  isFirstTime: boolean;
-}
-type stakingInfo= {
-  tokenMint: anchor.web3.PublicKey;
-  totalTokensStaked: anchor.BN;
-  totalTokensRewarded: anchor.BN;
-  stakingStartDate: anchor.BN;
-  allowClaiming: boolean;
-  authority: anchor.web3.PublicKey;
-}
-const PRECISION = 1_000_000; 
-const MONTH_DURATION = (30 * 24 * 60 * 60) * PRECISION;
-const DAY_DURATION = (24 * 60 * 60) * PRECISION;
-const DAILY_REWARDS = [
-  12671000000, 13014000000, 13356000000, 13699000000, 14041000000, 14384000000, 14726000000, 15068000000, 15411000000, 15753000000, 16096000000, 16438000000
-];
-const calculateAccumulatedRewards = (userData:userData,stakingInfo:stakingInfo):number => {
-  const stakedAmount = userData.totalStakingBalance.toNumber();
-  const totalStaked = stakingInfo.totalTokensStaked.toNumber();
-  const userShare = (stakedAmount * PRECISION) / totalStaked;
-  
-  const time_diff = (Math.floor(Date.now()/1000) - userData.stakeDate.toNumber()) * PRECISION;
-  console.log("time_diff",time_diff)
-  const stakingStartDate = stakingInfo.stakingStartDate.toNumber();
-
-  const stakeDurationDays = ((time_diff + (DAY_DURATION / 2)) / DAY_DURATION);
-
-
-  let rewardAccumulated = 0;
-  let remainingDays = stakeDurationDays;
-  let currentMonth = (((userData.stakeDate.toNumber() - stakingStartDate) * PRECISION + (MONTH_DURATION/2) )/MONTH_DURATION);
-   currentMonth = Math.floor(currentMonth); 
-  console.log("currentMonth",currentMonth)
-  while (remainingDays > 0 && currentMonth < DAILY_REWARDS.length) {
-    const dailyReward = DAILY_REWARDS[currentMonth];
-    let daysInMonth = Math.min(remainingDays, 30);
-    daysInMonth = Math.floor(daysInMonth);
-    rewardAccumulated += (userShare * dailyReward * daysInMonth)/PRECISION;
-    
-    remainingDays -= daysInMonth;
-    currentMonth += 1;
-  }
-  // Ensure minimum 1-day reward if stake_duration_days > 0 but reward_accumulated is 0
-  if (stakeDurationDays >= 1 && rewardAccumulated == 0) {
-    let daily_reward = DAILY_REWARDS[0]; // Use first month's reward rate
-    let min_reward = (userShare * daily_reward) / PRECISION;
-    rewardAccumulated = min_reward;
-  }
-  return rewardAccumulated;
-};
-
 async function getSolBalance(pg:Program<SolanaPresale>,address:anchor.web3.PublicKey):Promise<number>{
   let initialBalance: number;
   try {   
@@ -100,6 +46,16 @@ describe("solana presale testcases", async() => {
   const PRESALE_SEED = "solana_presale";
   const STAKING_SEED = "solana_staking";
   const DATA_SEED_STAKING = "staking_user_data";
+  const PAYMENT_MINT_SEED = "payment_mint";
+  const PAYMENT_DATA_SEED = "payment_data";
+  const REFERRAL_SEED = "referral";
+  const BONUS_SEED = "bonus_config";
+  const ROLES_SEED = "roles";
+  const ACTION_SEED = "timelock_action";
+  const MULTISIG_SEED = "multisig";
+  const WITHDRAWAL_SEED = "withdrawal_proposal";
+  const TREASURY_WALLET_SEED = "treasury_wallet";
+  const REWARD_SCHEDULE_SEED = "reward_schedule";
   const account1 = program.provider.publicKey
   const account2 = anchor.web3.Keypair.generate()
   const account3 = anchor.web3.Keypair.generate()
@@ -130,7 +86,39 @@ describe("solana presale testcases", async() => {
     program.programId
   );
 
+  const [rolesPda] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from(ROLES_SEED)],
+    program.programId
+  );
+
 const stakingReward = 100000000000000;
+
+  // pda of the next queued admin action
+  const nextActionPda = async () => {
+    const presaleData = await program.account.presaleInfo.fetch(presalePda)
+    const [timelockAction] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from(ACTION_SEED),presaleData.actionCount.toArrayLike(Buffer,"le",8)],
+      program.programId
+    );
+    return timelockAction
+  }
+
+  const treasuryWalletPda = (wallet:anchor.web3.PublicKey) => anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from(TREASURY_WALLET_SEED),wallet.toBuffer()],
+    program.programId
+  )[0]
+
+  // queues an admin action as the authority. the validator can't skip the timelock delay,
+  // so the tests only check that queued actions wait for it.
+  const queueAction = async (action:any, roles:anchor.web3.PublicKey = null) => {
+    const timelockAction = await nextActionPda()
+    await program.methods
+      .queueAction(action)
+      .accounts({signer:account1,presale:presalePda,timelockAction,roles})
+      .rpc();
+    return timelockAction
+  }
+
   before(async()=>{
     await airdropSol(account2.publicKey, 20*1e9); // 20 SOL
     await airdropSol(account3.publicKey, 20*1e9); // 20 SOL
@@ -242,6 +230,7 @@ const stakingReward = 100000000000000;
     // Add your test here.
     await program.methods.initializer(
       new BN(date), // startTime
+      new BN(date+30*24*60*60), // endTime
       new BN(368664), // pricePerTokenInSol
       new BN(79067) // pricePerTokenInUsdc
     )        
@@ -256,6 +245,8 @@ const stakingReward = 100000000000000;
     assert.equal(79067,Number(data.pricePerTokenInUsdc));
     assert.equal(true,data.isLive);
     assert.equal(true,data.isInitialized);
+    assert.equal(data.usdcMint.toBase58(),usdc.toBase58());
+    assert.equal(date+30*24*60*60,Number(data.endTime));
 
 
 
@@ -266,6 +257,110 @@ const stakingReward = 100000000000000;
   });
 
 
+  it("add usdc payment mint", async () => {
+    const [paymentMint] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from(PAYMENT_MINT_SEED),usdc.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .addPaymentMint(true,anchor.web3.PublicKey.default,new BN(100e6),new BN(0))
+      .accounts({
+        signer:account1,
+        presale:presalePda,
+        paymentMint,
+        paymentTokenMint:usdc,
+        presalePaymentAccount:presale_usdc_ata.address,
+      })
+      .rpc();
+
+    const data = await program.account.paymentMint.fetch(paymentMint)
+    assert.equal(data.paymentTokenMint.toBase58(),usdc.toBase58());
+    assert.equal(data.decimals,6);
+    assert.equal(data.usdPegged,true);
+    assert.equal(data.isActive,true);
+  });
+
+  it("create reward schedule", async () => {
+    const [rewardSchedule] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from(REWARD_SCHEDULE_SEED)],
+      program.programId
+    );
+    // 12 months of the daily rewards from the staking logic
+    const dailyRewards = [
+      1205350000, 1237979000, 1270512000, 1303141000, 1335674000, 1368302000,
+      1400836000, 1433369000, 1465998000, 1498531000, 1531159000, 1563693000,
+    ];
+    const context = {
+      signer:account1,
+      presale:presalePda,
+      staking:stakingPda,
+      rewardSchedule,
+      roles:rolesPda,
+    }
+    // the authority has to hold the staking manager role itself
+    try{
+      await program.methods
+        .createRewardSchedule(new BN(30*24*60*60),[new BN(1)])
+        .accounts({...context,roles:null})
+        .rpc();
+      assert(false)
+    }catch(e){
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("Unauthorized"))
+      }else{
+        throw e;
+      }
+    }
+    await program.methods
+      .grantRole({stakingManager:{}},account1)
+      .accounts({presale:presalePda,roles:rolesPda,signer:account1})
+      .rpc();
+    // periods are at most a year, longer ones could overflow the schedule's end time
+    try{
+      await program.methods
+        .createRewardSchedule(new BN(365*24*60*60+1),[new BN(1)])
+        .accounts(context)
+        .rpc();
+      assert(false)
+    }catch(e){
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("InvalidRewardSchedule"))
+      }else{
+        throw e;
+      }
+    }
+    await program.methods
+      .createRewardSchedule(new BN(30*24*60*60),dailyRewards.map((reward)=>new BN(reward*30)))
+      .accounts(context)
+      .rpc();
+
+    // a 13th month is appended, the first 12 stay as they are
+    await program.methods
+      .appendRewardPeriods([new BN(1563693000*30)])
+      .accounts(context)
+      .rpc();
+    const data = await program.account.rewardSchedule.fetch(rewardSchedule)
+    const stakingInfo = await program.account.stakingInfo.fetch(stakingPda)
+    assert.equal(data.startTime.toString(),stakingInfo.stakingStartDate.toString())
+    assert.equal(data.emissions.length,13)
+    assert.equal(data.emissions[0].toNumber(),dailyRewards[0]*30)
+    assert.equal(data.emissions[12].toNumber(),1563693000*30)
+
+    try{
+      await program.methods
+        .appendRewardPeriods([])
+        .accounts(context)
+        .rpc();
+      assert(false)
+    }catch(e){
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("InvalidRewardSchedule"))
+      }else{
+        throw e;
+      }
+    }
+  });
+
   it("transfer tokens to presale", async () => {
     const transferAmount = 400000000000000
     const from_ata = await getOrCreateAssociatedTokenAccount(
@@ -362,9 +457,6 @@ const stakingReward = 100000000000000;
       from:account2.publicKey,
       signer:account2.publicKey,
       presale:presalePda,
-      investor_usdc_account:userUsdcTokenAccount,
-      presaleUsdcAccount:presale_usdc_ata.address,
-      usdcMint:usdc,
       presaleTokenAccount:presale_ata,
       tokenMint:token,
       signerTokenAccount:reciever_ata,
@@ -376,7 +468,7 @@ const stakingReward = 100000000000000;
 console.log("beforeBalance",beforeBalance)
 const investAmount = 0.5*1e9;
     // Add your test here.
-    await program.methods.invest(new anchor.BN(investAmount),0)        
+    await program.methods.invest(new anchor.BN(investAmount),new BN(0),[],new BN(0))        
     .accounts(context)
     .signers([account2])
     .rpc();
@@ -407,14 +499,24 @@ const investAmount = 0.5*1e9;
     owner: account2.publicKey,
   });
   
+    const [paymentMintPda] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from(PAYMENT_MINT_SEED),usdc.toBuffer()],
+      program.programId
+    );
+    const [paymentDataPda] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from(PAYMENT_DATA_SEED),usdc.toBuffer(),account2.publicKey.toBuffer()],
+      program.programId
+    );
     const context = {
       data:dataPda,
       from:account2.publicKey,
       signer:account2.publicKey,
       presale:presalePda,
-      investor_usdc_account:userUsdcTokenAccount,
-      presaleUsdcAccount:presale_usdc_ata.address,
-      usdcMint:usdc,
+      paymentMint:paymentMintPda,
+      paymentData:paymentDataPda,
+      paymentTokenMint:usdc,
+      presalePaymentAccount:presale_usdc_ata.address,
+      signerPaymentAccount:userUsdcTokenAccount,
       presaleTokenAccount:presale_ata,
       tokenMint:token,
       signerTokenAccount:reciever_ata,
@@ -425,7 +527,7 @@ const investAmount = 0.5*1e9;
 
     // Add your test here.
     
-    await program.methods.invest(account2UsdcInvestment,1)        
+    await program.methods.invest(account2UsdcInvestment,new BN(0),[],new BN(0))        
     .accounts(context)
     .signers([account2])
     .rpc();
@@ -482,15 +584,12 @@ it("buy with sol and stake",async()=>{
         tokenMint:token,
         presaleTokenAccount:presale_ata,
         stakingTokenAccount:staking_ata,
-        usdcMint:usdc,
-        presaleUsdcAccount:presale_usdc_ata.address,
-        signerUsdAccount:userUsdcTokenAccount,
         tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
         systemProgram: anchor.web3.SystemProgram.programId,
         associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
       }
       // Add your test here.
-      await program.methods.buyAndStake(account2Investment,0)        
+      await program.methods.buyAndStake(account2Investment,new BN(0),[],new BN(0))        
       .accounts(context)
       .signers([account2])
 
@@ -543,6 +642,14 @@ const userUsdcTokenAccount = anchor.utils.token.associatedAddress({
       });
   
     
+      const [paymentMintPda] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from(PAYMENT_MINT_SEED),usdc.toBuffer()],
+        program.programId
+      );
+      const [paymentDataPda] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from(PAYMENT_DATA_SEED),usdc.toBuffer(),account2.publicKey.toBuffer()],
+        program.programId
+      );
       const context = {
         investmentData:dataPda,
         stakingData:stakingDataPda,
@@ -553,14 +660,17 @@ const userUsdcTokenAccount = anchor.utils.token.associatedAddress({
         tokenMint:token,
         presaleTokenAccount:presale_ata,
         stakingTokenAccount:staking_ata,
-        usdcMint:usdc,
-        presaleUsdcAccount:presale_usdc_ata.address,
+        paymentMint:paymentMintPda,
+        paymentData:paymentDataPda,
+        paymentTokenMint:usdc,
+        presalePaymentAccount:presale_usdc_ata.address,
+        signerPaymentAccount:userUsdcTokenAccount,
         tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
         systemProgram: anchor.web3.SystemProgram.programId,
         associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
       }
       // Add your test here.
-      await program.methods.buyAndStake(account2UsdcInvestment,1)        
+      await program.methods.buyAndStake(account2UsdcInvestment,new BN(0),[],new BN(0))        
       .accounts(context)
       .signers([account2])
 
@@ -603,14 +713,28 @@ it("invest using sol with new account ",async()=>{
 const presaleBalance = (await program.provider.connection.getTokenAccountBalance(presale_ata))
 console.log("presaleBalance",presaleBalance)
 
+  // account2 already bought, so it can refer account3
+  const [referrerDataPda] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from(DATA_SEED),account2.publicKey.toBuffer()],
+    program.programId
+  );
+  const [referralDataPda] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from(REFERRAL_SEED),account2.publicKey.toBuffer()],
+    program.programId
+  );
+  await program.methods
+    .setReferralBonus(500)
+    .accounts({presale:presalePda,signer:account1})
+    .rpc();
+
   const context = {
     data:dataPda,
     from:account3.publicKey,
     signer:account3.publicKey,
     presale:presalePda,
-    investor_usdc_account:userUsdcTokenAccount,
-    presaleUsdcAccount:presale_usdc_ata.address,
-    usdcMint:usdc,
+    referrer:account2.publicKey,
+    referrerData:referrerDataPda,
+    referralData:referralDataPda,
     presaleTokenAccount:presale_ata,
     tokenMint:token,
     signerTokenAccount:reciever_ata.address,
@@ -622,10 +746,18 @@ console.log("presaleBalance",presaleBalance)
 console.log("beforeBalance",beforeBalance)
 const investAmount = 0.5*1e9;
   // Add your test here.
-  await program.methods.invest(new anchor.BN(investAmount),0)        
+  await program.methods.invest(new anchor.BN(investAmount),new BN(0),[],new BN(0))        
   .accounts(context)
   .signers([account3])
   .rpc();
+
+  const referralData = await program.account.referralData.fetch(referralDataPda)
+  const referredData = await program.account.investmentData.fetch(dataPda)
+  assert.equal(referredData.referrer.toBase58(),account2.publicKey.toBase58())
+  assert.equal(Number(referralData.referredWallets),1)
+  assert.equal(Number(referralData.referredSolAmount),investAmount)
+  assert.equal(Number(referralData.referredTokens),Number(referredData.numberOfTokens))
+  assert.equal(Number(referralData.rewardTokens),Math.floor(Number(referredData.numberOfTokens)*500/10000))
   
   const afterPresaleBalance = (await program.provider.connection.getTokenAccountBalance(presale_ata))
   const data = await program.account.investmentData.fetch(dataPda)
@@ -651,14 +783,24 @@ const userUsdcTokenAccount = anchor.utils.token.associatedAddress({
   owner: account4.publicKey,
 });
 
+  const [paymentMintPda] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from(PAYMENT_MINT_SEED),usdc.toBuffer()],
+    program.programId
+  );
+  const [paymentDataPda] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from(PAYMENT_DATA_SEED),usdc.toBuffer(),account4.publicKey.toBuffer()],
+    program.programId
+  );
   const context = {
     data:dataPda,
     from:account4.publicKey,
     signer:account4.publicKey,
     presale:presalePda,
-    investor_usdc_account:userUsdcTokenAccount,
-    presaleUsdcAccount:presale_usdc_ata.address,
-    usdcMint:usdc,
+    paymentMint:paymentMintPda,
+    paymentData:paymentDataPda,
+    paymentTokenMint:usdc,
+    presalePaymentAccount:presale_usdc_ata.address,
+    signerPaymentAccount:userUsdcTokenAccount,
     presaleTokenAccount:presale_ata,
     tokenMint:token,
     signerTokenAccount:reciever_ata,
@@ -669,7 +811,7 @@ const userUsdcTokenAccount = anchor.utils.token.associatedAddress({
 
   // Add your test here.
   
-  await program.methods.invest(account2UsdcInvestment,1)        
+  await program.methods.invest(account2UsdcInvestment,new BN(0),[],new BN(0))        
   .accounts(context)
   .signers([account4])
   .rpc();
@@ -686,81 +828,206 @@ const userUsdcTokenAccount = anchor.utils.token.associatedAddress({
 })
 
 
-  it("withdraw sol",async()=>{
-    const newAuthority = account2.publicKey
+it("queue the oracle price feed",async()=>{
+  // sol keeps its fixed price until the queued feed is executed, see presale_lifecycle.ts
+  const priceFeed = anchor.web3.Keypair.generate().publicKey
+  const timelockAction = await queueAction({setPriceFeed:{priceFeed,maxPriceAge:new BN(60),maxConfidenceBps:100}})
+  try {
+    await program.methods
+      .executeAction()
+      .accounts({presale:presalePda,signer:account1,staking:stakingPda,timelockAction,roles:null})
+      .rpc();
+    assert(false);
+  }catch(e) {
+    if (e instanceof anchor.AnchorError){
+      assert(e.message.includes("TimelockNotExpired"))
+    }else{
+      throw e;
+    }
+  }
+  const presaleData = await program.account.presaleInfo.fetch(presalePda)
+  assert(presaleData.priceFeed.equals(anchor.web3.PublicKey.default))
+})
+
+  it("transfer authority",async()=>{
     const context = {
       presale:presalePda,
-      staking:stakingPda,
       signer:account1
     };
 
-     await program.methods
-      .updateAuthority(account2.publicKey)
-      .accounts(context)
-      .rpc();
-   /**
-    * 
- signer
- presale
- presaleUsdcAccount
- signerUsdcAccount
- usdcMint
- systemProgram
- tokenProgram
- associatedTokenProgram
-    */
-
-const userUsdcTokenAccount = await getOrCreateAssociatedTokenAccount(
-  program.provider.connection,
-  payer,
-  usdc,  // The mint address
-  newAuthority // Owner of the token account
-);
-    const startPresaleContext = {
-      
-      signer:newAuthority,
-      presale:presalePda,
-      presaleUsdcAccount:presale_usdc_ata.address,
-      signerUsdAccount:userUsdcTokenAccount.address,
-      usdcMint:usdc,
-      systemProgram: anchor.web3.SystemProgram.programId,
-      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
-      associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+    // the proposal waits for the default delay of 2 days
+    const timelockAction = await queueAction({proposeAuthority:{authority:account2.publicKey}})
+    const action = await program.account.timelockAction.fetch(timelockAction)
+    assert.equal(action.eta.toNumber(),action.queuedAt.toNumber()+2*24*60*60)
+    try {
+      await program.methods
+        .executeAction()
+        .accounts({...context,staking:stakingPda,timelockAction})
+        .rpc();
+      assert(false);
+    }catch(e) {
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("TimelockNotExpired"))
+      }else{
+        throw e;
+      }
     }
-    const beforeBalance = await getSolBalance(program,newAuthority)
-    await program.methods.adminWithdrawUsdcAndSol()        
-    .accounts(startPresaleContext)
-    .signers([account2])
 
-    .rpc();
-    const afterBalance = await getSolBalance(program,newAuthority)
-    const rentExemption = await program.provider.connection.getMinimumBalanceForRentExemption(program.account.presaleInfo.size)
-    assert.isTrue(afterBalance > beforeBalance+Number(account2Investment) - rentExemption);
-  })
+    // a cancelled action can't be executed
+    await program.methods
+      .cancelAction()
+      .accounts({...context,timelockAction})
+      .rpc();
+    try {
+      await program.methods
+        .executeAction()
+        .accounts({...context,staking:stakingPda,timelockAction})
+        .rpc();
+      assert(false);
+    }catch(e) {
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("ActionNotPending"))
+      }else{
+        throw e;
+      }
+    }
 
+    // nothing to accept without an executed proposal
+    try {
+      await program.methods
+        .acceptAuthority()
+        .accounts({
+          presale:presalePda,
+          staking:stakingPda,
+          signer:account2.publicKey
+        })
+        .signers([account2])
+        .rpc();
+      assert(false);
+    }catch(e) {
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("NoPendingAuthority"))
+      }else{
+        throw e;
+      }
+    }
+    const data = await program.account.presaleInfo.fetch(presalePda)
+    assert.equal(data.authority.toBase58(),account1.toBase58())
+    assert.equal(data.pendingAuthority.toBase58(),anchor.web3.PublicKey.default.toBase58())
+  });
 
+  it("set end time", async () => {
+    const context = {
+      presale:presalePda,
+      signer:account1
+    };
 
+    // can't shorten the presale below now
+    try {
+      await program.methods
+        .setEndTime(new BN(date-1))
+        .accounts(context)
+        .rpc();
+      assert(false);
+    }catch(e) {
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("WrongTime"))
+      }else{
+        throw e;
+      }
+    }
 
+    await program.methods
+      .setEndTime(new BN(date+60*24*60*60))
+      .accounts(context)
+      .rpc();
+    const data = await program.account.presaleInfo.fetch(presalePda)
+    assert.equal(date+60*24*60*60,Number(data.endTime))
+  });
 
+  it("set bonus tiers", async () => {
+    const [bonusConfigPda] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from(BONUS_SEED)],
+      program.programId
+    );
+    const context = {
+      presale:presalePda,
+      bonusConfig:bonusConfigPda,
+      signer:account1
+    };
 
+    // tiers have to be sorted by min tokens
+    try {
+      await program.methods
+        .setBonusTiers([
+          {minTokens:new BN(1000e5),bonusBps:1000},
+          {minTokens:new BN(100e5),bonusBps:500},
+        ],0,new BN(0))
+        .accounts(context)
+        .rpc();
+      assert(false);
+    }catch(e) {
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("InvalidBonusTiers"))
+      }else{
+        throw e;
+      }
+    }
 
+    await program.methods
+      .setBonusTiers([
+        {minTokens:new BN(100e5),bonusBps:500},
+        {minTokens:new BN(1000e5),bonusBps:1000},
+      ],200,new BN(48*60*60))
+      .accounts(context)
+      .rpc();
+    const data = await program.account.bonusConfig.fetch(bonusConfigPda)
+    assert.equal(data.tierCount,2)
+    assert.equal(Number(data.tiers[1].minTokens),1000e5)
+    assert.equal(data.tiers[1].bonusBps,1000)
+    assert.equal(data.earlyBonusBps,200)
+    assert.equal(Number(data.earlyBonusDuration),48*60*60)
+  });
 
   it("toggle Presale", async () => {
-
+    // account3 only gets the pauser role
+    await program.methods
+      .grantRole({pauser:{}},account3.publicKey)
+      .accounts({
+        presale:presalePda,
+        roles:rolesPda,
+        signer:account1
+      })
+      .rpc();
 
     const context = {
       presale:presalePda,
-      signer:account2.publicKey
+      roles:rolesPda,
+      signer:account3.publicKey
     };
 
      await program.methods
       .togglePresale(false)
       .accounts(context)
-      .signers([account2])
+      .signers([account3])
       .rpc();
       const data = await program.account.presaleInfo.fetch(presalePda)
       assert.equal(data.isLive,false)
 
+    try {
+      await program.methods
+        .queueAction({changePrice:{solPrice:new BN(1),usdcPrice:new BN(1)}})
+        .accounts({...context,timelockAction:await nextActionPda()})
+        .signers([account3])
+        .rpc();
+      assert(false);
+    }catch(e) {
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("Unauthorized"))
+      }else{
+        throw e;
+      }
+    }
   });
 
 
@@ -824,88 +1091,257 @@ const userUsdcTokenAccount = await getOrCreateAssociatedTokenAccount(
   
   
   
-  it("unstake_and_claim_rewards",async()=>{
-    const STAKING_DATA_SEED = "staking_user_data";
+  it("compound rewards",async()=>{
+    const [dataPda] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from(DATA_SEED_STAKING),account1.toBuffer()],
+      program.programId
+    );
+    const context = {
+      user:account1,
+      stakingData:dataPda,
+      staking:stakingPda,
+    }
 
+    // stakers have to opt in
     try{
-    const context1 = {
-      signer:account2.publicKey,
+      await program.methods.compound()
+      .accounts(context)
+      .rpc();
+      assert(false)
+    }catch(e){
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("AutoCompoundDisabled"))
+      }else{
+        throw e;
+      }
+    }
+    await program.methods.setAutoCompound(true)
+    .accounts({stakingData:dataPda,signer:account1})
+    .rpc();
+
+    // let some rewards accrue, anyone can crank the compounding
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+    const beforeData = await program.account.stakingData.fetch(dataPda);
+    const beforeInfo = await program.account.stakingInfo.fetch(stakingPda);
+    const compoundIx = await program.methods.compound()
+    .accounts(context)
+    .instruction();
+    // account3 pays for the transaction, the staker doesn't sign
+    await anchor.web3.sendAndConfirmTransaction(
+      program.provider.connection,
+      new anchor.web3.Transaction().add(compoundIx),
+      [account3]
+    );
+    const afterData = await program.account.stakingData.fetch(dataPda);
+    const afterInfo = await program.account.stakingInfo.fetch(stakingPda);
+    const compounded = afterData.rewardsClaimed.sub(beforeData.rewardsClaimed)
+    assert.isTrue(compounded.toNumber() > 0)
+    assert.equal(afterData.totalStakingBalance.toString(),beforeData.totalStakingBalance.add(compounded).toString())
+    assert.equal(afterInfo.totalTokensStaked.toString(),beforeInfo.totalTokensStaked.add(compounded).toString())
+
+    await program.methods.setAutoCompound(false)
+    .accounts({stakingData:dataPda,signer:account1})
+    .rpc();
+  })
+
+  const stakingContext = () => {
+    const [dataPda] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from(DATA_SEED_STAKING),account1.toBuffer()],
+      program.programId
+    );
+    return {
+      stakingData:dataPda,
+      from:account1,
       staking:stakingPda,
+      presale:presalePda,
+      stakingTokenAccount:anchor.utils.token.associatedAddress({mint: token, owner: stakingPda}),
+      signerTokenAccount:anchor.utils.token.associatedAddress({mint: token, owner: account1}),
+      signer:account1,
+      tokenMint:token,
     }
-    // Add your test here.
-    await program.methods.allowClaiming(true)        
-    .accounts(context1)
-    .signers([account2])
+  }
+
+  it("claim rewards",async()=>{
+    const context = stakingContext()
+    // account1 granted itself the staking manager role for the reward schedule
+    await program.methods.allowClaiming(true)
+    .accounts({signer:account1,presale:presalePda,staking:stakingPda,roles:rolesPda})
     .rpc();
-  
-  
-     const [dataPda] = anchor.web3.PublicKey.findProgramAddressSync(
-           [Buffer.from(STAKING_DATA_SEED),account1.toBuffer()],
-           program.programId
-         );
-        
-         const reciever_ata = anchor.utils.token.associatedAddress({
-           mint: token,
-           owner: account1,
-         });
-  
-  
-    
-  
-  
-         const staking_ata = anchor.utils.token.associatedAddress({
-           mint: token,
-           owner: stakingPda,
-         });
-     
-       
-         const context = {
-             stakingData:dataPda,
-          from:account1,
-           staking:stakingPda,
-           stakingTokenAccount:staking_ata,
-           signerTokenAccount:reciever_ata,
-           signer:account1,
-           tokenMint:token,
-           tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
-           systemProgram: anchor.web3.SystemProgram.programId,
-           associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
-         }
-         const beforeBalance = (await program.provider.connection.getTokenAccountBalance(reciever_ata))
-         
-         const userData = await program.account.stakingData.fetch(dataPda);
-         const stakingInfo = await program.account.stakingInfo.fetch(stakingPda);
-      
-         const rewards = calculateAccumulatedRewards(userData,stakingInfo);
-         console.log("rewards--",rewards);
-         // Add your test here.
-         await program.methods.unstakeAndClaimRewards()        
-         .accounts(context)
-         .rpc(); 
-         const afterBalance = (await program.provider.connection.getTokenAccountBalance(reciever_ata))
-         console.log("beforeBalance",beforeBalance.value.uiAmount)
-      console.log("afterBalance",afterBalance.value.uiAmount)
-  
-  
-  
-            //  assert.equal(Number(afterBalance.value.amount),Number(beforeBalance.value.amount)+stakingAmount.toNumber()+reward);
-        }catch(e) {
-        if (e instanceof anchor.AnchorError){
-          assert(e.message.includes("NoRewards"))
-            }else{
-              assert(false);
-            }
-        }
+
+    // let some rewards accrue
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+    const beforeData = await program.account.stakingData.fetch(context.stakingData);
+    const beforeBalance = (await program.provider.connection.getTokenAccountBalance(context.signerTokenAccount))
+    await program.methods.claimRewards()
+    .accounts(context)
+    .rpc();
+    const afterData = await program.account.stakingData.fetch(context.stakingData);
+    const afterBalance = (await program.provider.connection.getTokenAccountBalance(context.signerTokenAccount))
+    const claimed = afterData.rewardsClaimed.sub(beforeData.rewardsClaimed).toNumber()
+    assert.isTrue(claimed > 0)
+    assert.equal(Number(afterBalance.value.amount)-Number(beforeBalance.value.amount),claimed)
+    assert.equal(afterData.totalStakingBalance.toString(),beforeData.totalStakingBalance.toString())
+    assert.equal(afterData.pendingRewards.toNumber(),0)
   })
 
-  it("withdraw tokens",async()=>{
-    
+  it("unstake part of the stake",async()=>{
+    const context = stakingContext()
+    const beforeData = await program.account.stakingData.fetch(context.stakingData);
+    const beforeInfo = await program.account.stakingInfo.fetch(stakingPda);
+    const beforeBalance = (await program.provider.connection.getTokenAccountBalance(context.signerTokenAccount))
 
+    // can't unstake more than staked
+    try{
+      await program.methods.unstake(beforeData.totalStakingBalance.addn(1))
+      .accounts(context)
+      .rpc();
+      assert(false)
+    }catch(e){
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("InsufficientFunds"))
+      }else{
+        throw e;
+      }
+    }
+
+    const amount = beforeData.totalStakingBalance.divn(2)
+    await program.methods.unstake(amount)
+    .accounts(context)
+    .rpc();
+    const afterData = await program.account.stakingData.fetch(context.stakingData);
+    const afterInfo = await program.account.stakingInfo.fetch(stakingPda);
+    const afterBalance = (await program.provider.connection.getTokenAccountBalance(context.signerTokenAccount))
+    assert.equal(afterData.totalStakingBalance.toString(),beforeData.totalStakingBalance.sub(amount).toString())
+    assert.equal(afterInfo.totalTokensStaked.toString(),beforeInfo.totalTokensStaked.sub(amount).toString())
+    assert.isTrue(afterData.isFirstTime)
+    assert.isTrue(Number(afterBalance.value.amount) >= Number(beforeBalance.value.amount)+amount.toNumber())
+  })
+
+  it("unstake_and_claim_rewards",async()=>{
+    const context = stakingContext()
+    const beforeData = await program.account.stakingData.fetch(context.stakingData);
+    const beforeBalance = (await program.provider.connection.getTokenAccountBalance(context.signerTokenAccount))
+    await program.methods.unstakeAndClaimRewards()
+    .accounts(context)
+    .rpc();
+    const afterData = await program.account.stakingData.fetch(context.stakingData);
+    const afterBalance = (await program.provider.connection.getTokenAccountBalance(context.signerTokenAccount))
+    const rewards = afterData.rewardsClaimed.sub(beforeData.rewardsClaimed).toNumber()
+    assert.equal(afterData.totalStakingBalance.toNumber(),0)
+    assert.isFalse(afterData.isFirstTime)
+    assert.equal(
+      Number(afterBalance.value.amount)-Number(beforeBalance.value.amount),
+      beforeData.totalStakingBalance.toNumber()+rewards
+    )
+  })
+
+  it("withdraw sol",async()=>{
+    const [multisig] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from(MULTISIG_SEED)],
+      program.programId
+    );
+
+    // treasury wallets and approvers only change through the timelock, executing the
+    // queued actions and the withdrawals themselves is covered by the lifecycle tests
+    const treasuryAction = await queueAction({addTreasuryWallet:{wallet:account1}})
+    try{
+      await program.methods
+        .addTreasuryWallet(account1)
+        .accounts({signer:account1,presale:presalePda,timelockAction:treasuryAction,treasuryWallet:treasuryWalletPda(account1)})
+        .rpc();
+      assert(false)
+    }catch(e){
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("TimelockNotExpired"))
+      }else{
+        throw e;
+      }
+    }
+    // 2 of 2 approvers, the set is padded to 10 keys
+    const approversAction = await queueAction({setApprovers:{
+      approvers:[account1,account3.publicKey,...Array(8).fill(anchor.web3.PublicKey.default)],
+      approverCount:2,
+      threshold:2,
+    }})
+    try{
+      await program.methods
+        .setApprovers()
+        .accounts({signer:account1,presale:presalePda,timelockAction:approversAction,multisig})
+        .rpc();
+      assert(false)
+    }catch(e){
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("TimelockNotExpired"))
+      }else{
+        throw e;
+      }
+    }
+
+    // nothing can be proposed before the approvers are set
+    const [proposal] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from(WITHDRAWAL_SEED),new BN(0).toArrayLike(Buffer,"le",8)],
+      program.programId
+    );
+    try{
+      await program.methods
+        .proposeWithdrawal(anchor.web3.PublicKey.default,account1,new BN(1))
+        .accounts({signer:account1,multisig,proposal,treasuryWallet:treasuryWalletPda(account1),presale:presalePda,paymentMint:null})
+        .rpc();
+      assert(false)
+    }catch(e){
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("AccountNotInitialized"))
+      }else{
+        throw e;
+      }
+    }
+
+    // the presale runs for another 60 days, it can only be finalized early at a hard cap
+    const stopContext = {presale:presalePda,signer:account1}
+    try{
+      await program.methods.finalizePresale()
+      .accounts(stopContext)
+      .rpc();
+      assert(false)
+    }catch(e){
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("PresaleNotEnded"))
+      }else{
+        throw e;
+      }
+    }
+    // the bonus and referral rewards count against the token cap along with the sold tokens
+    const capData = await program.account.presaleInfo.fetch(presalePda)
+    const committedTokens = capData.totalTokensSold.add(capData.totalBonusTokens).add(capData.referralRewards)
+    await program.methods.setCaps(committedTokens,new BN(0),new BN(0),new BN(0))
+    .accounts(stopContext)
+    .rpc();
+    await program.methods.finalizePresale()
+    .accounts(stopContext)
+    .rpc();
+    assert.isTrue((await program.account.presaleInfo.fetch(presalePda)).isSuccessful)
+
+    // the presale was created with the current layout
+    try{
+      await program.methods.migratePresale(usdc)
+      .accounts({signer:account1,presale:presalePda})
+      .rpc();
+      assert(false)
+    }catch(e){
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("AlreadyMigrated"))
+      }else{
+        throw e;
+      }
+    }
+  })
+
+  it("withdraw tokens",async()=>{
     const reciever_ata = await getOrCreateAssociatedTokenAccount(
       program.provider.connection,
       payer,
       token,  // The mint address
-      account2.publicKey // Owner of the token account
+      account1 // Owner of the token account
     );
     const presale_ata = anchor.utils.token.associatedAddress({
       mint: token,
@@ -914,32 +1350,78 @@ const userUsdcTokenAccount = await getOrCreateAssociatedTokenAccount(
 
     const context = {
       presaleTokenAccount:presale_ata,
-      stakingTokenAccount:staking_ata.address,
-      signerTokenAccount:reciever_ata.address,
+      destination:account1,
+      destinationTokenAccount:reciever_ata.address,
+      treasuryWallet:treasuryWalletPda(account1),
       presale:presalePda,
-      signer:account2.publicKey,
+      signer:account1,
+      roles:rolesPda,
       tokenMint:token,
       tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
       systemProgram: anchor.web3.SystemProgram.programId,
       associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
     }
 
-    // Add your test here.
-    await program.methods.adminWithdrawTokens()        
-    .accounts(context)
-    .signers([account2])
-    .rpc();
-    await program.methods.adminWithdrawStakingTokens()        
-    .accounts(context)
-    .signers([account2])
-    .rpc();
-    const balance = (await program.provider.connection.getTokenAccountBalance(reciever_ata.address))
-    const presaleBalance = (await program.provider.connection.getTokenAccountBalance(presale_ata))
-    const data = await program.account.presaleInfo.fetch(presalePda)
-    const stakingInfo = await program.account.stakingInfo.fetch(stakingPda);
-    // assert.equal(Number(balance.value.amount),Number(mintAmount* 10 ** metadata.decimals - Number(data.totalTokensSold) + Number(stakingInfo.totalTokensStaked)))
-    assert.equal(Number(presaleBalance.value.amount),Number(0))
+    // the authority needs the treasurer role for token withdrawals
+    try{
+      await program.methods.adminWithdrawTokens(new BN(1))
+      .accounts(context)
+      .rpc();
+      assert(false)
+    }catch(e){
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("Unauthorized"))
+      }else{
+        throw e;
+      }
+    }
+    await program.methods
+      .grantRole({treasurer:{}},account1)
+      .accounts({presale:presalePda,roles:rolesPda,signer:account1})
+      .rpc();
+
+    // the treasury wallet queued in "withdraw sol" isn't allowlisted yet
+    try{
+      await program.methods.adminWithdrawTokens(new BN(1))
+      .accounts(context)
+      .rpc();
+      assert(false)
+    }catch(e){
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("AccountNotInitialized"))
+      }else{
+        throw e;
+      }
+    }
+
+    // surplus staking tokens wait for the timelock too
+    const timelockAction = await queueAction({withdrawStakingTokens:{destination:account1}},rolesPda)
+    try{
+      await program.methods
+        .executeAction()
+        .accounts({
+          signer:account1,
+          presale:presalePda,
+          staking:stakingPda,
+          timelockAction,
+          roles:rolesPda,
+          tokenMint:token,
+          stakingTokenAccount:staking_ata.address,
+          rewardSchedule:anchor.web3.PublicKey.findProgramAddressSync([Buffer.from(REWARD_SCHEDULE_SEED)],program.programId)[0],
+          destination:account1,
+          destinationTokenAccount:reciever_ata.address,
+          // not allowlisted yet, the timelock fails first
+          treasuryWallet:null,
+        })
+        .rpc();
+      assert(false)
+    }catch(e){
+      if (e instanceof anchor.AnchorError){
+        assert(e.message.includes("TimelockNotExpired"))
+      }else{
+        throw e;
+      }
+    }
   })
 
- 
 });